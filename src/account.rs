@@ -27,12 +27,8 @@ pub struct ScalableAccount {
     pub name: String,
     pub email: String,
     pub password: String,
-}
-
-#[derive(Clone)]
-pub enum Account {
-    Ethereum(EthereumAccount),
-    Kraken(KrakenAccount),
-    Nordigen(NordigenAccount),
-    Scalable(ScalableAccount),
+    // Second factor, base32 as shown when setting up an authenticator app
+    pub totp_secret: Option<String>,
+    // All broker portfolios if not given
+    pub portfolio_id: Option<String>,
 }
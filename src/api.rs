@@ -1,17 +1,21 @@
-use super::account::Account;
 use super::asset::Asset;
-use super::blockchain::get_assets_of_ethereum_account;
+use super::currency::FiatCurrency;
+use super::date::parse_date;
 use super::domainconfig::DomainConfig;
 use super::error::ApiError;
-use super::kraken::get_assets_of_kraken_account;
-use super::nordigen::{get_assets_of_nordigen_account, NordigenCache};
-use super::scalable::{get_assets_of_scalable_account, ScalableCache};
+use super::fx::{get_fx_rate, parse_currency};
+use super::history::{unix_now, FundHistoryDto, FundSnapshot};
+use super::portfolio::{spawn_refresh_tasks, AccountSnapshot, PortfolioCache};
+use super::provider::AccountProvider;
+use rocket::fairing::AdHoc;
 use rocket::serde::{json::json, json::Json, json::Value, Serialize};
 use rocket::Config;
 use rocket::{Build, Rocket, State};
 use serde_json::map::Map;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct AssetDto {
     pub name: String,
     pub description: String,
@@ -19,6 +23,69 @@ pub struct AssetDto {
     pub real_growth: f32,
     pub units: f32,
     pub unit_price: f32,
+    // Last known value of an account that could not be fetched
+    pub stale: bool,
+    // Historical return per timeframe, where the provider reports them
+    pub returns: BTreeMap<String, f32>,
+    // Where the trades are known, e.g. for Kraken
+    pub cost_basis: Option<f32>,
+    pub realized_pnl: Option<f32>,
+    pub unrealized_pnl: Option<f32>,
+    // Held, but no price is known, the value counts as zero
+    pub unpriced: bool,
+}
+
+impl AssetDto {
+    pub fn from_asset(asset: &dyn Asset) -> Self {
+        let pnl = asset.get_pnl();
+        Self {
+            name: asset.get_name(),
+            nominal_growth: asset.get_growth().get_nominal_growth(),
+            real_growth: asset.get_growth().get_real_growth(),
+            units: asset.get_units(),
+            unit_price: asset.get_unit_price(),
+            description: asset.get_description(),
+            stale: false,
+            returns: asset.get_returns().into_iter().collect(),
+            cost_basis: pnl.as_ref().map(|p| p.cost_basis),
+            realized_pnl: pnl.as_ref().map(|p| p.realized),
+            unrealized_pnl: pnl.as_ref().map(|p| p.unrealized),
+            unpriced: asset.is_unpriced(),
+        }
+    }
+
+    // Prices are kept in USD, rate converts them into another currency
+    pub fn in_currency(&self, rate: f32) -> Self {
+        Self {
+            unit_price: self.unit_price * rate,
+            cost_basis: self.cost_basis.map(|c| c * rate),
+            realized_pnl: self.realized_pnl.map(|p| p * rate),
+            unrealized_pnl: self.unrealized_pnl.map(|p| p * rate),
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Serialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountState {
+    Ok,
+    Stale,
+    // The source answered, but no longer knows the account
+    Unavailable,
+    Error,
+    Pending,
+}
+
+#[derive(Serialize, Clone)]
+pub struct AccountStatusDto {
+    pub name: String,
+    pub kind: String,
+    pub status: AccountState,
+    pub message: Option<String>,
+    pub age_seconds: Option<u64>,
+    // Of the credential expiring first, negative once expired
+    pub expires_in_days: Option<i64>,
 }
 
 #[derive(Serialize)]
@@ -29,6 +96,7 @@ pub struct FundDto {
     pub nominal_yearly_growth: f32,
     pub real_yearly_growth: f32,
     pub assets: Vec<AssetDto>,
+    pub accounts: Vec<AccountStatusDto>,
     pub target_size: Option<f32>,
 }
 
@@ -37,6 +105,7 @@ impl FundDto {
         name: String,
         icon: Option<String>,
         assets: Vec<AssetDto>,
+        accounts: Vec<AccountStatusDto>,
         target_size: Option<f32>,
     ) -> Self {
         let eps = 0.00001; // Division by zero avoidance
@@ -50,151 +119,429 @@ impl FundDto {
             .map(|x| x.units * x.unit_price * (1. + x.real_growth))
             .sum();
         Self {
-            name: name,
-            icon: icon,
-            balance: balance,
+            name,
+            icon,
+            balance,
             nominal_yearly_growth: (balance_in_one_year + eps) / (balance + eps) - 1.,
             real_yearly_growth: (real_balance_in_one_year + eps) / (balance + eps) - 1.,
-            assets: assets,
-            target_size: target_size,
+            assets,
+            accounts,
+            target_size,
         }
     }
 }
 
-#[get("/")]
-pub async fn get_overview(
-    domainconfig: &State<DomainConfig>,
-    nordigen_cache: &State<NordigenCache>,
-    scalable_cache: &State<ScalableCache>,
-) -> Result<Json<Vec<FundDto>>, ApiError> {
-    let mut fund_dtos = Vec::new();
+#[derive(Serialize)]
+pub struct BalanceDto {
+    pub balance_type: String,
+    pub amount: f32,
+    pub currency: String,
+    pub reference_date: Option<String>,
+}
 
-    for fund in domainconfig.funds.iter() {
-        let mut collected_assets = Vec::new();
-        for account in fund.accounts.iter() {
-            let mut assets: Vec<Box<dyn Asset>> = match account {
-                Account::Kraken(kraken_account) => {
-                    get_assets_of_kraken_account(domainconfig, kraken_account)
-                        .await?
-                        .iter_mut()
-                        .map(|a| Box::new(a.clone()) as Box<dyn Asset>)
-                        .collect::<Vec<Box<dyn Asset>>>()
-                }
-                Account::Ethereum(eth_account) => {
-                    get_assets_of_ethereum_account(domainconfig, eth_account)
-                        .await?
-                        .iter_mut()
-                        .map(|x| Box::new(x.clone()) as Box<dyn Asset>)
-                        .collect::<Vec<Box<dyn Asset>>>()
-                }
-                Account::Nordigen(nordigen_account) => {
-                    get_assets_of_nordigen_account(nordigen_cache, nordigen_account)
-                        .await?
-                        .iter_mut()
-                        .map(|x| Box::new(x.clone()) as Box<dyn Asset>)
-                        .collect::<Vec<Box<dyn Asset>>>()
-                }
-                Account::Scalable(scalable_account) => {
-                    get_assets_of_scalable_account(scalable_cache, scalable_account)
-                        .await?
-                        .iter_mut()
-                        .map(|x| Box::new(x.clone()) as Box<dyn Asset>)
-                        .collect::<Vec<Box<dyn Asset>>>()
-                }
-            };
-            collected_assets.append(&mut assets);
+#[derive(Serialize, Clone)]
+pub struct TransactionDto {
+    pub id: Option<String>,
+    // booked or pending
+    pub status: String,
+    pub booking_date: Option<String>,
+    pub value_date: Option<String>,
+    pub amount: f32,
+    pub currency: String,
+    pub counterparty: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct OverviewDto {
+    pub currency: String,
+    pub funds: Vec<FundDto>,
+    pub warnings: Vec<String>,
+    // Age of the oldest data in the response
+    pub age_seconds: Option<u64>,
+}
+
+fn overview_from_cache(
+    domainconfig: &DomainConfig,
+    cache: &PortfolioCache,
+    currency: &FiatCurrency,
+    rate: f32,
+) -> OverviewDto {
+    let mut snapshots: HashMap<String, AccountSnapshot> = HashMap::new();
+    for account in domainconfig.get_fund_accounts() {
+        snapshots.insert(account.get_name(), cache.get(&account));
+    }
+
+    let funds = domainconfig
+        .funds
+        .iter()
+        .map(|fund| {
+            let accounts: Vec<(String, &AccountSnapshot)> = fund
+                .accounts
+                .iter()
+                .filter_map(|a| snapshots.get(&a.get_name()).map(|s| (a.get_name(), s)))
+                .collect();
+            FundDto::new(
+                fund.name.clone(),
+                fund.icon.clone(),
+                accounts
+                    .iter()
+                    .flat_map(|(_, s)| s.assets.iter().map(|a| a.in_currency(rate)))
+                    .collect(),
+                accounts
+                    .iter()
+                    .map(|(name, s)| s.to_status_dto(name))
+                    .collect(),
+                fund.target_size,
+            )
+        })
+        .collect();
+
+    let mut warnings: Vec<String> = snapshots
+        .values()
+        .filter_map(|s| s.message.clone())
+        .collect();
+    for (name, snapshot) in snapshots.iter() {
+        warnings.append(&mut snapshot.get_expiry_warnings(name, domainconfig.expiry_warning));
+        for asset in snapshot.assets.iter().filter(|a| a.unpriced) {
+            warnings.push(format!(
+                "No price for {} ({}) of account {}",
+                asset.name, asset.description, name
+            ));
         }
-        let asset_dtos = collected_assets
-            .iter()
-            .map(|a| AssetDto {
-                name: a.get_name(),
-                nominal_growth: a.get_growth().get_nominal_growth(),
-                real_growth: a.get_growth().get_real_growth(),
-                units: a.get_units(),
-                unit_price: a.get_unit_price(),
-                description: a.get_description(),
-            })
-            .collect();
-        fund_dtos.push(FundDto::new(
-            fund.name.clone(),
-            fund.icon.clone(),
-            asset_dtos,
-            fund.target_size,
-        ));
     }
+    warnings.sort();
+
+    OverviewDto {
+        currency: currency.to_string(),
+        funds,
+        warnings,
+        age_seconds: snapshots
+            .values()
+            .filter_map(|s| s.get_age())
+            .max()
+            .map(|a| a.as_secs()),
+    }
+}
+
+// In the requested currency, or the base currency. Without an exchange rate
+// the last known one is used, or the overview is shown in USD.
+async fn overview_in_currency(
+    domainconfig: &DomainConfig,
+    cache: &PortfolioCache,
+    currency: Option<String>,
+) -> Result<OverviewDto, ApiError> {
+    let currency = match currency {
+        Some(c) => parse_currency(&c)?,
+        None => domainconfig.base_currency,
+    };
+    let (currency, rate, warning) = match get_fx_rate(&FiatCurrency::USD, &currency).await {
+        Ok(rate) => {
+            cache.set_last_rate(&currency, rate);
+            (currency, rate, None)
+        }
+        Err(e) => match cache.get_last_rate(&currency) {
+            Some(rate) => (
+                currency,
+                rate,
+                Some(format!(
+                    "Using the last known exchange rate for {}: {}",
+                    currency.get_code(),
+                    e.get_message()
+                )),
+            ),
+            None => (
+                FiatCurrency::USD,
+                1.0,
+                Some(format!(
+                    "Shown in USD, no exchange rate for {}: {}",
+                    currency.get_code(),
+                    e.get_message()
+                )),
+            ),
+        },
+    };
+    let mut overview = overview_from_cache(domainconfig, cache, &currency, rate);
+    if let Some(warning) = warning {
+        overview.warnings.insert(0, warning);
+    }
+    Ok(overview)
+}
 
-    Ok(Json(fund_dtos))
+// Only the funds, the shape / always had
+#[get("/?<currency>")]
+pub async fn get_funds(
+    domainconfig: &State<Arc<DomainConfig>>,
+    cache: &State<Arc<PortfolioCache>>,
+    currency: Option<String>,
+) -> Result<Json<Vec<FundDto>>, ApiError> {
+    Ok(Json(
+        overview_in_currency(domainconfig, cache, currency)
+            .await?
+            .funds,
+    ))
+}
+
+#[get("/overview?<currency>")]
+pub async fn get_overview(
+    domainconfig: &State<Arc<DomainConfig>>,
+    cache: &State<Arc<PortfolioCache>>,
+    currency: Option<String>,
+) -> Result<Json<OverviewDto>, ApiError> {
+    Ok(Json(
+        overview_in_currency(domainconfig, cache, currency).await?,
+    ))
 }
 
-#[get("/metrics")]
+#[get("/metrics?<currency>")]
 pub async fn get_metrics(
-    domainconfig: &State<DomainConfig>,
-    nordigen_cache: &State<NordigenCache>,
-    scalable_cache: &State<ScalableCache>,
+    domainconfig: &State<Arc<DomainConfig>>,
+    cache: &State<Arc<PortfolioCache>>,
+    currency: Option<String>,
 ) -> Result<String, ApiError> {
     let mut result = String::from(
-        "# HELP get_rich_slow_asset Asset value in USD.\n
+        "# HELP get_rich_slow_asset Asset value in the currency of the currency label.\n
         # TYPE get_rich_slow_asset gauge\n
         # HELP get_rich_slow_growth Growth of asset.\n
-        # TYPE get_rich_slow_growth gauge\n",
+        # TYPE get_rich_slow_growth gauge\n
+        # HELP get_rich_slow_account_up Whether the last fetch of the account succeeded.\n
+        # TYPE get_rich_slow_account_up gauge\n
+        # HELP get_rich_slow_credential_expiry_seconds Seconds until credentials expire, negative once expired.\n
+        # TYPE get_rich_slow_credential_expiry_seconds gauge\n
+        # HELP get_rich_slow_cache_lookups Cache lookups by result.\n
+        # TYPE get_rich_slow_cache_lookups counter\n",
     );
-    for fund in domainconfig.funds.iter() {
+    let overview = overview_in_currency(domainconfig, cache, currency).await?;
+    for fund in overview.funds.iter() {
+        for a in fund.assets.iter() {
+            result.push_str(&format!(
+                "get_rich_slow_asset {{fund=\"{}\", name=\"{}\", description=\"{}\", currency=\"{}\"}} {}\n",
+                fund.name,
+                a.name,
+                a.description,
+                overview.currency,
+                a.units * a.unit_price,
+            ));
+            result.push_str(&format!(
+                "get_rich_slow_growth {{fund=\"{}\", name=\"{}\", description=\"{}\", currency=\"{}\"}} {}\n",
+                fund.name,
+                a.name,
+                a.description,
+                overview.currency,
+                a.real_growth * a.units * a.unit_price,
+            ));
+        }
         for account in fund.accounts.iter() {
-            let assets: Vec<Box<dyn Asset>> = match account {
-                Account::Kraken(kraken_account) => {
-                    get_assets_of_kraken_account(domainconfig, kraken_account)
-                        .await?
-                        .iter_mut()
-                        .map(|a| Box::new(a.clone()) as Box<dyn Asset>)
-                        .collect::<Vec<Box<dyn Asset>>>()
-                }
-                Account::Ethereum(eth_account) => {
-                    get_assets_of_ethereum_account(domainconfig, eth_account)
-                        .await?
-                        .iter_mut()
-                        .map(|x| Box::new(x.clone()) as Box<dyn Asset>)
-                        .collect::<Vec<Box<dyn Asset>>>()
-                }
-                Account::Nordigen(nordigen_account) => {
-                    get_assets_of_nordigen_account(nordigen_cache, nordigen_account)
-                        .await?
-                        .iter_mut()
-                        .map(|x| Box::new(x.clone()) as Box<dyn Asset>)
-                        .collect::<Vec<Box<dyn Asset>>>()
-                }
-                Account::Scalable(scalable_account) => {
-                    get_assets_of_scalable_account(scalable_cache, scalable_account)
-                        .await?
-                        .iter_mut()
-                        .map(|x| Box::new(x.clone()) as Box<dyn Asset>)
-                        .collect::<Vec<Box<dyn Asset>>>()
+            result.push_str(&format!(
+                "get_rich_slow_account_up {{fund=\"{}\", account=\"{}\", kind=\"{}\"}} {}\n",
+                fund.name,
+                account.name,
+                account.kind,
+                (account.status == AccountState::Ok) as u8,
+            ));
+        }
+    }
+    for account in domainconfig.get_fund_accounts() {
+        for expiry in cache.get(&account).expiries.iter() {
+            result.push_str(&format!(
+                "get_rich_slow_credential_expiry_seconds {{account=\"{}\", kind=\"{}\", credential=\"{}\"}} {}\n",
+                account.get_name(),
+                account.get_kind(),
+                expiry.credential,
+                AccountSnapshot::get_expiry_seconds(expiry),
+            ));
+        }
+    }
+    let mut described = Vec::new();
+    for metric in domainconfig.providers.get_metrics() {
+        if !described.contains(&metric.name) {
+            result.push_str(&format!(
+                "# HELP get_rich_slow_{} {}\n# TYPE get_rich_slow_{} gauge\n",
+                metric.name, metric.help, metric.name,
+            ));
+            described.push(metric.name.clone());
+        }
+        let labels: Vec<String> = metric
+            .labels
+            .iter()
+            .map(|(label, value)| format!("{}=\"{}\"", label, value))
+            .collect();
+        result.push_str(&format!(
+            "get_rich_slow_{} {{{}}} {}\n",
+            metric.name,
+            labels.join(", "),
+            metric.value,
+        ));
+    }
+    for (cache, stats) in domainconfig.get_cache_stats() {
+        for (result_name, count) in [
+            ("hit", stats.hits),
+            ("stale", stats.stale_hits),
+            ("miss", stats.misses),
+        ] {
+            result.push_str(&format!(
+                "get_rich_slow_cache_lookups {{cache=\"{}\", result=\"{}\"}} {}\n",
+                cache, result_name, count,
+            ));
+        }
+    }
+    Ok(result)
+}
+
+// Time in unix seconds
+#[get("/history?<fund>&<from>&<to>")]
+pub async fn get_history(
+    domainconfig: &State<Arc<DomainConfig>>,
+    fund: Option<String>,
+    from: Option<u64>,
+    to: Option<u64>,
+) -> Result<Json<Vec<FundHistoryDto>>, ApiError> {
+    if domainconfig.history.is_none() {
+        return Err(ApiError::new(
+            &"History is disabled, set history-file".to_string(),
+        ));
+    }
+    let domainconfig = domainconfig.inner().clone();
+    // Reads the whole file
+    let history = tokio::task::spawn_blocking(move || match &domainconfig.history {
+        Some(history) => history.query(fund.as_deref(), from, to),
+        None => Ok(Vec::new()),
+    })
+    .await
+    .map_err(|e| ApiError::new(&e.to_string()))??;
+    Ok(Json(history))
+}
+
+fn find_account(
+    domainconfig: &DomainConfig,
+    name: &String,
+) -> Result<Arc<dyn AccountProvider>, ApiError> {
+    domainconfig
+        .get_fund_accounts()
+        .into_iter()
+        .find(|a| a.get_name() == *name)
+        .ok_or(ApiError::new(&format!("Account {} not found", name)))
+}
+
+// Dates have to be YYYY-MM-DD and exist
+fn check_date(date: &Option<String>) -> Result<(), ApiError> {
+    if let Some(date) = date {
+        if parse_date(date).is_none() {
+            return Err(ApiError::new(&format!(
+                "Invalid date {}, expected YYYY-MM-DD",
+                date
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[get("/accounts/<name>/balances")]
+pub async fn get_account_balances(
+    domainconfig: &State<Arc<DomainConfig>>,
+    name: String,
+) -> Result<Json<Vec<BalanceDto>>, ApiError> {
+    let account = find_account(domainconfig, &name)?;
+    Ok(Json(account.get_balances(domainconfig).await?))
+}
+
+#[get("/accounts/<name>/transactions?<from>&<to>")]
+pub async fn get_account_transactions(
+    domainconfig: &State<Arc<DomainConfig>>,
+    name: String,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<Json<Vec<TransactionDto>>, ApiError> {
+    check_date(&from)?;
+    check_date(&to)?;
+    let account = find_account(domainconfig, &name)?;
+    let transactions = account
+        .get_transactions(domainconfig, from.as_ref(), to.as_ref())
+        .await?;
+    // Not every bank filters pending transactions by date
+    Ok(Json(
+        transactions
+            .into_iter()
+            .filter(|t| {
+                let date = t.booking_date.as_ref().or(t.value_date.as_ref());
+                match date {
+                    None => true,
+                    Some(d) => {
+                        from.as_ref().is_none_or(|from| d >= from)
+                            && to.as_ref().is_none_or(|to| d <= to)
+                    }
                 }
-            };
-            for a in assets {
-                let units = a.get_units();
-                let unit_price = a.get_unit_price();
-                result.push_str(&format!(
-                    "get_rich_slow_asset {{fund=\"{}\", name=\"{}\", description=\"{}\"}} {}\n",
-                    fund.name,
-                    a.get_name(),
-                    a.get_description(),
-                    units * unit_price,
-                ));
-                result.push_str(&format!(
-                    "get_rich_slow_growth {{fund=\"{}\", name=\"{}\", description=\"{}\"}} {}\n",
-                    fund.name,
-                    a.get_name(),
-                    a.get_description(),
-                    a.get_growth().get_real_growth() * units * unit_price,
-                ));
+            })
+            .collect(),
+    ))
+}
+
+// Why a snapshot would not show all funds at their full value,
+// e.g. an account not fetched yet or an asset without a price
+fn get_incomplete_reasons(overview: &OverviewDto) -> Vec<String> {
+    let mut reasons = Vec::new();
+    for fund in overview.funds.iter() {
+        for account in fund.accounts.iter() {
+            if account.status != AccountState::Ok && account.status != AccountState::Stale {
+                reasons.push(format!("account {} is not fetched", account.name));
             }
         }
+        for asset in fund.assets.iter().filter(|a| a.unpriced) {
+            reasons.push(format!(
+                "no price for {} ({})",
+                asset.name, asset.description
+            ));
+        }
+    }
+    reasons.sort();
+    reasons.dedup();
+    reasons
+}
+
+async fn record_history(domainconfig: Arc<DomainConfig>, cache: Arc<PortfolioCache>) {
+    if domainconfig.history.is_none() {
+        return;
+    }
+    let mut interval = tokio::time::interval(domainconfig.history_interval);
+    // Give the refresh tasks a chance to fetch every account first
+    interval.reset();
+    loop {
+        interval.tick().await;
+        // Recorded in the base currency
+        let overview = match overview_in_currency(&domainconfig, &cache, None).await {
+            Ok(o) => o,
+            Err(e) => {
+                println!("Failed to record history: {}", e.get_message());
+                continue;
+            }
+        };
+        // Would show as a drop in the history
+        let reasons = get_incomplete_reasons(&overview);
+        if !reasons.is_empty() {
+            println!("Skipping history snapshot: {}", reasons.join(", "));
+            continue;
+        }
+        let funds = overview
+            .funds
+            .iter()
+            .map(FundSnapshot::from_fund_dto)
+            .collect();
+        let domainconfig = domainconfig.clone();
+        let result = tokio::task::spawn_blocking(move || match &domainconfig.history {
+            Some(history) => history.append(unix_now(), &overview.currency, funds),
+            None => Ok(()),
+        })
+        .await
+        .map_err(|e| ApiError::new(&e.to_string()))
+        .and_then(|r| r);
+        if let Err(e) = result {
+            println!("Failed to record history: {}", e.get_message());
+        }
     }
-    Ok(result)
 }
 
 #[get("/block")]
-pub async fn get_block(domainconfig: &State<DomainConfig>) -> Result<Value, ApiError> {
+pub async fn get_block(domainconfig: &State<Arc<DomainConfig>>) -> Result<Value, ApiError> {
     let mut map = Map::new();
 
     let tasks: Vec<(String, _)> = domainconfig
@@ -232,12 +579,28 @@ pub fn get_rocket_build(domainconfig: DomainConfig) -> Rocket<Build> {
         address: address.into(),
         ..Config::debug_default()
     };
-    // Default nordigen cache expiring to half a day
-    let nordigen_cache = NordigenCache::new(domainconfig.nordigen_cache_hours.unwrap_or(3));
-    let scalable_cache = ScalableCache::new();
+    let domainconfig = Arc::new(domainconfig);
+    let cache = Arc::new(PortfolioCache::new());
+    let tasks = (domainconfig.clone(), cache.clone());
     rocket::custom(config)
         .manage(domainconfig)
-        .manage(nordigen_cache)
-        .manage(scalable_cache)
-        .mount("/", routes![get_overview, get_block, get_metrics])
+        .manage(cache)
+        .attach(AdHoc::on_liftoff("Background refresh", |_| {
+            Box::pin(async move {
+                spawn_refresh_tasks(tasks.0.clone(), tasks.1.clone());
+                tokio::spawn(record_history(tasks.0, tasks.1));
+            })
+        }))
+        .mount(
+            "/",
+            routes![
+                get_funds,
+                get_overview,
+                get_block,
+                get_metrics,
+                get_history,
+                get_account_balances,
+                get_account_transactions
+            ],
+        )
 }
@@ -1,5 +1,13 @@
 use super::growth::Growth;
 
+// In USD, of the units with a known cost
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct ProfitAndLoss {
+    pub cost_basis: f32,
+    pub realized: f32,
+    pub unrealized: f32,
+}
+
 #[derive(Clone)]
 pub struct GenericAsset {
     apy: f32,
@@ -7,6 +15,10 @@ pub struct GenericAsset {
     description: String,
     units: f32,
     unit_price: f32,
+    // Historical return per timeframe, e.g. ("one_year", 0.1)
+    returns: Vec<(String, f32)>,
+    pnl: Option<ProfitAndLoss>,
+    unpriced: bool,
 }
 
 impl GenericAsset {
@@ -23,8 +35,28 @@ impl GenericAsset {
             description: description,
             units: units,
             unit_price: unit_price,
+            returns: Vec::new(),
+            pnl: None,
+            unpriced: false,
         }
     }
+
+    pub fn with_returns(mut self, returns: Vec<(String, f32)>) -> Self {
+        self.returns = returns;
+        self
+    }
+
+    pub fn with_pnl(mut self, pnl: ProfitAndLoss) -> Self {
+        self.pnl = Some(pnl);
+        self
+    }
+
+    // Held, but without a known price, the unit price is zero
+    pub fn as_unpriced(mut self) -> Self {
+        self.unpriced = true;
+        self.pnl = None;
+        self
+    }
 }
 
 pub trait Asset: Send {
@@ -33,6 +65,17 @@ pub trait Asset: Send {
     fn get_unit_price(&self) -> f32;
     fn get_units(&self) -> f32;
     fn get_description(&self) -> String;
+    // Only known for some providers
+    fn get_returns(&self) -> Vec<(String, f32)> {
+        Vec::new()
+    }
+    // Only known where the trades are imported
+    fn get_pnl(&self) -> Option<ProfitAndLoss> {
+        None
+    }
+    fn is_unpriced(&self) -> bool {
+        false
+    }
 }
 
 impl Asset for GenericAsset {
@@ -55,4 +98,16 @@ impl Asset for GenericAsset {
     fn get_description(&self) -> String {
         self.description.clone()
     }
+
+    fn get_returns(&self) -> Vec<(String, f32)> {
+        self.returns.clone()
+    }
+
+    fn get_pnl(&self) -> Option<ProfitAndLoss> {
+        self.pnl.clone()
+    }
+
+    fn is_unpriced(&self) -> bool {
+        self.unpriced
+    }
 }
@@ -0,0 +1,134 @@
+use super::super::cryptoprice::get_token_price;
+use super::super::error::ApiError;
+use super::super::ethereum::EthereumChain;
+use super::abi::get_bep20_abi;
+use super::defiasset::DefiAsset;
+use web3::contract::Contract as Web3Contract;
+use web3::transports::Http;
+use web3::types::{Address, U256};
+use web3::Web3;
+
+// Plain BEP20 / ERC20 tokens. Works on every EVM chain,
+// as the token interface is the same everywhere.
+pub async fn get_bep20_assets(
+    web3: &Web3<Http>,
+    chain: &EthereumChain,
+    wallet_address: &Address,
+    contract_addresses: &Vec<Address>,
+) -> Result<Vec<DefiAsset>, ApiError> {
+    let mut res = Vec::new();
+
+    for contract_address in contract_addresses.iter() {
+        let smart = Web3Contract::new(web3.eth(), *contract_address, get_bep20_abi());
+
+        let balance: U256 = smart
+            .query(
+                "balanceOf",
+                (*wallet_address,),
+                None,
+                web3::contract::Options::default(),
+                None,
+            )
+            .await?;
+
+        if balance.is_zero() {
+            continue;
+        }
+
+        let decimals: u8 = smart
+            .query(
+                "decimals",
+                (),
+                None,
+                web3::contract::Options::default(),
+                None,
+            )
+            .await?;
+        let balance_float = to_units(balance, decimals);
+
+        let symbol: String = smart
+            .query("symbol", (), None, web3::contract::Options::default(), None)
+            .await?;
+
+        let price = get_token_price(&symbol).await;
+        res.push(to_token_asset(chain, symbol, balance_float, price));
+    }
+
+    Ok(res)
+}
+
+// Token units of a raw balance. Divided while still a U256, any contract
+// can return a balance beyond u128::MAX.
+fn to_units(balance: U256, decimals: u8) -> f64 {
+    let to_f64 = |value: U256| {
+        value
+            .0
+            .iter()
+            .rev()
+            .fold(0., |result, word| result * 2f64.powi(64) + *word as f64)
+    };
+    match U256::from(10).checked_pow(U256::from(decimals)) {
+        Some(unit) => to_f64(balance / unit) + to_f64(balance % unit) / to_f64(unit),
+        // More than 77 decimals
+        None => to_f64(balance) / 10f64.powi(decimals as i32),
+    }
+}
+
+// Any contract can be configured, a token without a price is still listed
+// instead of failing the other assets of the account
+fn to_token_asset(
+    chain: &EthereumChain,
+    symbol: String,
+    units: f64,
+    price: Result<f32, ApiError>,
+) -> DefiAsset {
+    let asset = |price: f32| {
+        DefiAsset::new(
+            0.,
+            symbol.clone(),
+            units as f32,
+            price,
+            chain.to_str().to_string(),
+        )
+    };
+    match price {
+        Ok(price) => asset(price),
+        Err(e) => {
+            println!("No price for token {}: {}", symbol, e.get_message());
+            asset(0.).as_unpriced()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::asset::Asset;
+    use super::*;
+
+    #[test]
+    fn test_to_units() {
+        assert_eq!(to_units(U256::from(1_500_000_000_000_000_000u64), 18), 1.5);
+        assert_eq!(to_units(U256::from(42), 0), 42.);
+        assert_eq!(to_units(U256::MAX, 0), 2f64.powi(256));
+        assert!((to_units(U256::MAX, 18) / 1.157920892373162e59 - 1.).abs() < 1e-12);
+        assert!(to_units(U256::MAX, 255) < 1e-100);
+    }
+
+    #[test]
+    fn test_unpriced_token() {
+        let chain = EthereumChain::BinanceSmartChain;
+        let priced = to_token_asset(&chain, "CAKE".to_string(), 2., Ok(3.));
+        let unpriced = to_token_asset(
+            &chain,
+            "SCAM".to_string(),
+            1000.,
+            Err(ApiError::new(&"No price".to_string())),
+        );
+        assert_eq!((priced.get_unit_price(), priced.is_unpriced()), (3., false));
+        assert_eq!(
+            (unpriced.get_units(), unpriced.get_unit_price()),
+            (1000., 0.)
+        );
+        assert!(unpriced.is_unpriced());
+    }
+}
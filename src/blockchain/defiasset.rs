@@ -8,6 +8,7 @@ pub struct DefiAsset {
     token_equivalent: f32,
     token_price: f32,
     description: String,
+    unpriced: bool,
 }
 
 impl DefiAsset {
@@ -24,8 +25,16 @@ impl DefiAsset {
             token_equivalent: token_equivalent,
             token_price: token_price,
             description: description,
+            unpriced: false,
         }
     }
+
+    // Held, but without a known price, the token price is zero
+    pub fn as_unpriced(mut self) -> Self {
+        self.unpriced = true;
+        self.token_price = 0.;
+        self
+    }
 }
 
 impl Asset for DefiAsset {
@@ -48,4 +57,8 @@ impl Asset for DefiAsset {
     fn get_description(&self) -> String {
         self.description.clone()
     }
+
+    fn is_unpriced(&self) -> bool {
+        self.unpriced
+    }
 }
@@ -0,0 +1,64 @@
+use super::super::error::ApiError;
+use super::abi::{get_bep20_abi, get_uniswap_v2_pair_abi};
+use web3::contract::Contract as Web3Contract;
+use web3::contract::Options;
+use web3::transports::Http;
+use web3::types::{Address, U256};
+use web3::Web3;
+
+async fn get_token_info(web3: &Web3<Http>, token: Address) -> Result<(String, f64), ApiError> {
+    let smart = Web3Contract::new(web3.eth(), token, get_bep20_abi());
+    let symbol: String = smart
+        .query("symbol", (), None, Options::default(), None)
+        .await?;
+    let decimals: u8 = smart
+        .query("decimals", (), None, Options::default(), None)
+        .await?;
+    Ok((symbol, decimals as f64))
+}
+
+fn to_float(amount: U256, decimals: f64) -> f64 {
+    amount.as_u128() as f64 / (10 as f64).powf(decimals)
+}
+
+// Price of one `symbol` token in units of the other token of a
+// Uniswap V2 style pool, taken from the pool reserves.
+// Returns the price and the symbol of the other token.
+pub async fn get_pair_price(
+    web3: &Web3<Http>,
+    pair_address: &Address,
+    symbol: &String,
+) -> Result<(f32, String), ApiError> {
+    let pair = Web3Contract::new(web3.eth(), *pair_address, get_uniswap_v2_pair_abi());
+    let (reserve0, reserve1, _): (U256, U256, U256) = pair
+        .query("getReserves", (), None, Options::default(), None)
+        .await?;
+    let token0: Address = pair
+        .query("token0", (), None, Options::default(), None)
+        .await?;
+    let token1: Address = pair
+        .query("token1", (), None, Options::default(), None)
+        .await?;
+
+    let (symbol0, decimals0) = get_token_info(web3, token0).await?;
+    let (symbol1, decimals1) = get_token_info(web3, token1).await?;
+    let amount0 = to_float(reserve0, decimals0);
+    let amount1 = to_float(reserve1, decimals1);
+    if amount0 == 0. || amount1 == 0. {
+        return Err(ApiError::new(&format!(
+            "Pool {:?} has no liquidity",
+            pair_address
+        )));
+    }
+
+    if symbol0.eq_ignore_ascii_case(symbol) {
+        Ok(((amount1 / amount0) as f32, symbol1))
+    } else if symbol1.eq_ignore_ascii_case(symbol) {
+        Ok(((amount0 / amount1) as f32, symbol0))
+    } else {
+        Err(ApiError::new(&format!(
+            "Pool {:?} is a {}/{} pool, not one for {}",
+            pair_address, symbol0, symbol1, symbol
+        )))
+    }
+}
@@ -1,16 +1,49 @@
 mod abi;
 mod alpaca;
 mod benqi;
+mod bep20;
 mod defiasset;
+mod dex;
 mod venus;
 
 use super::account::EthereumAccount;
+use super::asset::Asset;
+use super::cache::{default_cache_settings, CacheSettings, CacheStats, TtlCache};
+use super::config::Account as AccountConfig;
 use super::cryptoprice::get_token_price;
-use super::domainconfig::DomainConfig;
+use super::domainconfig::{parse_address, DomainConfig};
 use super::error::ApiError;
 use super::ethereum::{EthereumChain, EthereumNode};
+use super::provider::{boxed, require, AccountProvider};
+use std::sync::OnceLock;
 use web3::types::Address;
 
+pub use dex::get_pair_price;
+
+pub struct EthereumCache {
+    // Assets per chain and wallet address
+    assets: TtlCache<(String, Address), Vec<defiasset::DefiAsset>>,
+}
+
+impl EthereumCache {
+    pub fn new(default_settings: CacheSettings) -> Self {
+        Self {
+            assets: TtlCache::new(default_settings),
+        }
+    }
+
+    pub fn get_stats(&self) -> Vec<(String, CacheStats)> {
+        vec![("ethereum-assets".to_string(), self.assets.get_stats())]
+    }
+}
+
+// The settings of each chain are passed with every lookup
+static ETHEREUM_CACHE: OnceLock<EthereumCache> = OnceLock::new();
+
+fn ethereum_cache() -> &'static EthereumCache {
+    ETHEREUM_CACHE.get_or_init(|| EthereumCache::new(default_cache_settings()))
+}
+
 #[derive(Clone, PartialEq)]
 pub enum EthDefiToken {
     Bep20,
@@ -45,6 +78,21 @@ async fn get_native_asset(
 pub async fn get_assets_of_ethereum_account(
     domainconfig: &DomainConfig,
     account: &EthereumAccount,
+) -> Result<Vec<defiasset::DefiAsset>, ApiError> {
+    let kind = account.chain.to_identifier().to_string();
+    ethereum_cache()
+        .assets
+        .get_or_fetch_with(
+            (kind.clone(), account.wallet_address),
+            domainconfig.get_cache_settings(&kind),
+            || fetch_assets(domainconfig, account),
+        )
+        .await
+}
+
+async fn fetch_assets(
+    domainconfig: &DomainConfig,
+    account: &EthereumAccount,
 ) -> Result<Vec<defiasset::DefiAsset>, ApiError> {
     let nodes_for_chain: Vec<&EthereumNode> = domainconfig
         .eth_nodes
@@ -65,6 +113,12 @@ pub async fn get_assets_of_ethereum_account(
         .map(|x| (x.1.clone(), x.2))
         .collect();
 
+    let bep20_contracts: Vec<Address> = contracts_on_account_chain
+        .iter()
+        .filter(|x| x.0 == EthDefiToken::Bep20)
+        .map(|x| x.1)
+        .collect::<Vec<Address>>();
+
     let venus_contracts: Vec<Address> = contracts_on_account_chain
         .iter()
         .filter(|x| x.0 == EthDefiToken::Venus)
@@ -90,6 +144,16 @@ pub async fn get_assets_of_ethereum_account(
         if native.is_err() {
             continue;
         }
+        let t = bep20::get_bep20_assets(
+            &node.web3,
+            &account.chain,
+            &account.wallet_address,
+            &bep20_contracts,
+        )
+        .await;
+        if t.is_err() {
+            continue;
+        }
         let v = if account.chain == EthereumChain::BinanceSmartChain {
             venus::get_venus_assets(&node.web3, &account.wallet_address, &venus_contracts).await
         } else {
@@ -116,6 +180,7 @@ pub async fn get_assets_of_ethereum_account(
         }
         one_node_worked = true;
         assets.append(&mut native.ok().unwrap());
+        assets.append(&mut t.ok().unwrap());
         assets.append(&mut v.ok().unwrap());
         assets.append(&mut a.ok().unwrap());
         assets.append(&mut b.ok().unwrap());
@@ -129,3 +194,40 @@ pub async fn get_assets_of_ethereum_account(
 
     Ok(assets)
 }
+
+#[rocket::async_trait]
+impl AccountProvider for EthereumAccount {
+    fn from_config(name: &String, config: &AccountConfig) -> Result<Self, String> {
+        let chain = match EthereumChain::from_identifier(&config.kind) {
+            Some(chain) => chain,
+            None => return Err(format!("Unexpected blockchain identifier: {}", config.kind)),
+        };
+        let address = require(&config.address, "address", name, &config.kind)?;
+        Ok(Self {
+            name: name.clone(),
+            chain: chain,
+            wallet_address: parse_address(&address)?,
+        })
+    }
+
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_kind(&self) -> String {
+        self.chain.to_identifier().to_string()
+    }
+
+    fn get_cache_stats() -> Vec<(String, CacheStats)> {
+        ethereum_cache().get_stats()
+    }
+
+    async fn get_assets(
+        &self,
+        domainconfig: &DomainConfig,
+    ) -> Result<Vec<Box<dyn Asset>>, ApiError> {
+        Ok(boxed(
+            get_assets_of_ethereum_account(domainconfig, self).await?,
+        ))
+    }
+}
@@ -0,0 +1,310 @@
+use super::error::ApiError;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CacheConfig {
+    #[serde(rename = "ttl-minutes")]
+    pub ttl_minutes: Option<u64>,
+    #[serde(rename = "stale-minutes")]
+    pub stale_minutes: Option<u64>,
+}
+
+#[derive(Clone, Copy)]
+pub struct CacheSettings {
+    // Values younger than ttl are served without asking the source
+    pub ttl: Duration,
+    // Expired values are still served for this long if the source fails
+    // (or, for background revalidation, while refreshing)
+    pub stale: Duration,
+}
+
+impl CacheSettings {
+    pub fn new(ttl: Duration, stale: Duration) -> Self {
+        Self {
+            ttl: ttl,
+            stale: stale,
+        }
+    }
+
+    pub fn from_config(config: Option<&CacheConfig>, default: CacheSettings) -> Self {
+        match config {
+            None => default,
+            Some(c) => Self {
+                ttl: c
+                    .ttl_minutes
+                    .map(|m| Duration::from_secs(m * 60))
+                    .unwrap_or(default.ttl),
+                stale: c
+                    .stale_minutes
+                    .map(|m| Duration::from_secs(m * 60))
+                    .unwrap_or(default.stale),
+            },
+        }
+    }
+}
+
+// For account kinds without settings of their own
+pub fn default_cache_settings() -> CacheSettings {
+    CacheSettings::new(Duration::from_secs(60), Duration::from_secs(3600))
+}
+
+pub enum CacheLookup<V> {
+    Fresh(V),
+    Stale(V),
+    Miss,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub stale_hits: u64,
+    pub misses: u64,
+}
+
+struct Entry<V> {
+    value: V,
+    expires: Instant,
+    stale_until: Instant,
+    // A background revalidation is running
+    refreshing: bool,
+}
+
+pub struct TtlCache<K, V> {
+    settings: CacheSettings,
+    entries: Mutex<HashMap<K, Entry<V>>>,
+    hits: AtomicU64,
+    stale_hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<K, V> TtlCache<K, V>
+where
+    K: Eq + Hash + Clone + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    pub fn new(settings: CacheSettings) -> Self {
+        Self {
+            settings: settings,
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            stale_hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> CacheLookup<V> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        let lookup = match entries.get(key) {
+            Some(e) if now < e.expires => CacheLookup::Fresh(e.value.clone()),
+            Some(e) if now < e.stale_until => CacheLookup::Stale(e.value.clone()),
+            Some(_) => {
+                entries.remove(key);
+                CacheLookup::Miss
+            }
+            None => CacheLookup::Miss,
+        };
+        match lookup {
+            CacheLookup::Fresh(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            CacheLookup::Stale(_) => self.stale_hits.fetch_add(1, Ordering::Relaxed),
+            CacheLookup::Miss => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        lookup
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        self.insert_with(key, value, self.settings)
+    }
+
+    pub fn insert_with(&self, key: K, value: V, settings: CacheSettings) {
+        let expires = Instant::now() + settings.ttl;
+        self.entries.lock().unwrap().insert(
+            key,
+            Entry {
+                value: value,
+                expires: expires,
+                stale_until: expires + settings.stale,
+                refreshing: false,
+            },
+        );
+    }
+
+    pub fn remove(&self, key: &K) {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    // Marks the entry as refreshing, false if it already is or is gone
+    fn start_refresh(&self, key: &K) -> bool {
+        match self.entries.lock().unwrap().get_mut(key) {
+            Some(e) if !e.refreshing => {
+                e.refreshing = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // The old value is kept until its stale time is over
+    fn end_refresh(&self, key: &K) {
+        if let Some(e) = self.entries.lock().unwrap().get_mut(key) {
+            e.refreshing = false;
+        }
+    }
+
+    pub fn get_stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            stale_hits: self.stale_hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    pub async fn get_or_fetch<F, Fut>(&self, key: K, fetch: F) -> Result<V, ApiError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, ApiError>>,
+    {
+        self.get_or_fetch_with(key, self.settings, fetch).await
+    }
+
+    // Fresh values are returned as they are. Stale values are refetched,
+    // but still returned if fetching fails.
+    pub async fn get_or_fetch_with<F, Fut>(
+        &self,
+        key: K,
+        settings: CacheSettings,
+        fetch: F,
+    ) -> Result<V, ApiError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, ApiError>>,
+    {
+        let stale = match self.get(&key) {
+            CacheLookup::Fresh(v) => return Ok(v),
+            CacheLookup::Stale(v) => Some(v),
+            CacheLookup::Miss => None,
+        };
+        match (fetch().await, stale) {
+            (Ok(v), _) => {
+                self.insert_with(key, v.clone(), settings);
+                Ok(v)
+            }
+            (Err(e), Some(v)) => {
+                println!("Serving stale value after error: {}", e.get_message());
+                Ok(v)
+            }
+            (Err(e), None) => Err(e),
+        }
+    }
+
+    // Stale-while-revalidate: stale values are returned immediately
+    // while a background task fetches the new value.
+    pub async fn get_or_revalidate<F, Fut>(&'static self, key: K, fetch: F) -> Result<V, ApiError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, ApiError>> + Send + 'static,
+    {
+        match self.get(&key) {
+            CacheLookup::Fresh(v) => Ok(v),
+            CacheLookup::Stale(v) => {
+                // Keep other requests from starting the same refresh
+                if self.start_refresh(&key) {
+                    let future = fetch();
+                    tokio::spawn(async move {
+                        match future.await {
+                            Ok(v) => self.insert(key, v),
+                            Err(e) => {
+                                println!("Revalidation failed: {}", e.get_message());
+                                self.end_refresh(&key);
+                            }
+                        }
+                    });
+                }
+                Ok(v)
+            }
+            CacheLookup::Miss => {
+                let v = fetch().await?;
+                self.insert(key, v.clone());
+                Ok(v)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn settings(ttl_ms: u64, stale_ms: u64) -> CacheSettings {
+        CacheSettings::new(
+            Duration::from_millis(ttl_ms),
+            Duration::from_millis(stale_ms),
+        )
+    }
+
+    #[test]
+    fn test_lookup() {
+        let cache: TtlCache<String, u32> = TtlCache::new(settings(50, 50));
+        let key = "a".to_string();
+        assert!(matches!(cache.get(&key), CacheLookup::Miss));
+        cache.insert(key.clone(), 1);
+        assert!(matches!(cache.get(&key), CacheLookup::Fresh(1)));
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(matches!(cache.get(&key), CacheLookup::Stale(1)));
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(matches!(cache.get(&key), CacheLookup::Miss));
+
+        cache.insert_with(key.clone(), 2, settings(1000, 0));
+        assert!(matches!(cache.get(&key), CacheLookup::Fresh(2)));
+
+        let stats = cache.get_stats();
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.stale_hits, 1);
+        assert_eq!(stats.misses, 2);
+    }
+
+    #[tokio::test]
+    async fn test_get_or_fetch_serves_stale_on_error() {
+        let cache: TtlCache<String, u32> = TtlCache::new(settings(0, 1000));
+        let key = "a".to_string();
+        let v = cache.get_or_fetch(key.clone(), || async { Ok(1) }).await;
+        assert_eq!(v.unwrap(), 1);
+        let v = cache
+            .get_or_fetch(key.clone(), || async {
+                Err(ApiError::new(&"down".to_string()))
+            })
+            .await;
+        assert_eq!(v.unwrap(), 1);
+        let v = cache.get_or_fetch(key.clone(), || async { Ok(2) }).await;
+        assert_eq!(v.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_revalidation_failure_keeps_stale_value() {
+        let cache: &'static TtlCache<String, u32> =
+            Box::leak(Box::new(TtlCache::new(settings(0, 1000))));
+        let key = "a".to_string();
+        cache.insert(key.clone(), 1);
+        let v = cache
+            .get_or_revalidate(key.clone(), || async {
+                Err(ApiError::new(&"down".to_string()))
+            })
+            .await;
+        assert_eq!(v.unwrap(), 1);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(matches!(cache.get(&key), CacheLookup::Stale(1)));
+        let v = cache
+            .get_or_revalidate(key.clone(), || async { Ok(2) })
+            .await;
+        assert_eq!(v.unwrap(), 1);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(cache.get_stats().hits, 0);
+        assert!(matches!(cache.get(&key), CacheLookup::Stale(2)));
+    }
+}
@@ -1,3 +1,6 @@
+use super::cache::{CacheConfig, CacheSettings};
+use super::cryptoprice::PriceConfig;
+use super::fx::FxConfig;
 use serde::Deserialize;
 use serde_yaml::from_str;
 use std::collections::HashMap;
@@ -17,6 +20,10 @@ pub struct Account {
     pub account_id: Option<String>, // Nordigen
     pub email: Option<String>,   // Scalable
     pub password: Option<String>, // Scalable
+    #[serde(rename = "totp-secret")]
+    pub totp_secret: Option<String>, // Scalable
+    #[serde(rename = "portfolio-id")]
+    pub portfolio_id: Option<String>, // Scalable
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -28,6 +35,15 @@ pub struct Fund {
     pub target_size: Option<f32>,
 }
 
+// Nordigen user secrets, used to replace expired refresh tokens
+#[derive(Deserialize, Debug, Clone)]
+pub struct NordigenConfig {
+    #[serde(rename = "secret-id")]
+    pub secret_id: String,
+    #[serde(rename = "secret-key")]
+    pub secret_key: String,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct Configuration {
     pub accounts: HashMap<String, Account>,
@@ -39,13 +55,56 @@ pub struct Configuration {
     pub port: u16,
     #[serde(rename = "nordigen-cache-hours")]
     pub nordigen_cache_hours: Option<u64>,
+    #[serde(rename = "nordigen-quota-file")]
+    pub nordigen_quota_file: Option<String>,
+    #[serde(rename = "kraken-ledger-file")]
+    pub kraken_ledger_file: Option<String>,
     #[serde(rename = "listen-address")]
     pub listen_address: String,
+    #[serde(rename = "max-concurrent-requests")]
+    pub max_concurrent_requests: Option<usize>,
+    // Per account kind, plus "prices"
+    pub cache: Option<HashMap<String, CacheConfig>>,
+    #[serde(rename = "refresh-minutes")]
+    pub refresh_minutes: Option<HashMap<String, u64>>,
+    #[serde(rename = "history-file")]
+    pub history_file: Option<String>,
+    #[serde(rename = "history-interval-minutes")]
+    pub history_interval_minutes: Option<u64>,
+    pub prices: Option<PriceConfig>,
+    #[serde(rename = "base-currency")]
+    pub base_currency: Option<String>,
+    pub fx: Option<FxConfig>,
+    pub nordigen: Option<NordigenConfig>,
+    #[serde(rename = "expiry-warning-days")]
+    pub expiry_warning_days: Option<u64>,
+}
+
+impl Configuration {
+    // Settings of the cache of an account kind, default where not configured
+    pub fn get_cache_settings(&self, kind: &str, default: CacheSettings) -> CacheSettings {
+        CacheSettings::from_config(self.cache.as_ref().and_then(|c| c.get(kind)), default)
+    }
+}
+
+// Allow paths relative to the home directory, like ~/.get-rich-slow-history
+pub fn expand_home(path: &String) -> String {
+    match path.strip_prefix("~/") {
+        None => path.clone(),
+        Some(rest) => format!(
+            "{}/{}",
+            env::var("HOME").expect("HOME variable undefined"),
+            rest
+        ),
+    }
+}
+
+pub fn get_config_path() -> String {
+    expand_home(&"~/.get-rich-slow.yaml".to_string())
 }
 
 pub fn read_config() -> Configuration {
-    let home = env::var("HOME").expect("HOME variable undefined");
-    let content = fs::read_to_string(&format!("{}/.get-rich-slow.yaml", home))
-        .expect("Failed to read ~/.get-rich-slow.yaml");
+    let content =
+        fs::read_to_string(get_config_path()).expect("Failed to read ~/.get-rich-slow.yaml");
     from_str(&content).expect("Failed to parse ~/.get-rich-slow.yaml")
 }
@@ -1,33 +1,476 @@
+use super::blockchain::get_pair_price;
+use super::cache::{CacheSettings, CacheStats, TtlCache};
+use super::domainconfig::parse_address;
 use super::error::ApiError;
-use reqwest::get;
+use super::ethereum::{EthereumChain, EthereumNode};
+use serde::Deserialize;
 use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::time::Duration;
+use web3::types::Address;
+
+const BINANCE_URL: &str = "https://api.binance.com";
+const KRAKEN_URL: &str = "https://api.kraken.com";
+const COINGECKO_URL: &str = "https://api.coingecko.com";
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct PriceConfig {
+    // Order in which the sources are asked, first success wins
+    pub sources: Option<Vec<String>>,
+    #[serde(rename = "binance-url")]
+    pub binance_url: Option<String>,
+    #[serde(rename = "kraken-url")]
+    pub kraken_url: Option<String>,
+    #[serde(rename = "coingecko-url")]
+    pub coingecko_url: Option<String>,
+    pub overrides: Option<HashMap<String, PriceOverrideConfig>>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct PriceOverrideConfig {
+    pub sources: Option<Vec<String>>,
+    // Look up this symbol instead, e.g. BTCB -> BTC
+    pub symbol: Option<String>,
+    // Fixed price, e.g. for stable coins
+    pub fixed: Option<f32>,
+    #[serde(rename = "coingecko-id")]
+    pub coingecko_id: Option<String>,
+    // Uniswap V2 style pool for the dex source
+    pub chain: Option<String>,
+    pub pool: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PriceSource {
+    Binance,
+    Kraken,
+    CoinGecko,
+    Dex,
+}
+
+impl PriceSource {
+    pub fn from_identifier(identifier: &str) -> Option<Self> {
+        match identifier {
+            "binance" => Some(Self::Binance),
+            "kraken" => Some(Self::Kraken),
+            "coingecko" => Some(Self::CoinGecko),
+            "dex" => Some(Self::Dex),
+            _ => None,
+        }
+    }
+
+    pub fn to_identifier(&self) -> &str {
+        match *self {
+            Self::Binance => "binance",
+            Self::Kraken => "kraken",
+            Self::CoinGecko => "coingecko",
+            Self::Dex => "dex",
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct PriceOverride {
+    sources: Option<Vec<PriceSource>>,
+    symbol: Option<String>,
+    fixed: Option<f32>,
+    coingecko_id: Option<String>,
+    pool: Option<(EthereumChain, Address)>,
+}
+
+pub struct PriceOracle {
+    sources: Vec<PriceSource>,
+    binance_url: String,
+    kraken_url: String,
+    coingecko_url: String,
+    overrides: HashMap<String, PriceOverride>,
+    nodes: Vec<EthereumNode>,
+}
+
+fn parse_sources(identifiers: &Vec<String>) -> Result<Vec<PriceSource>, String> {
+    identifiers
+        .iter()
+        .map(|s| {
+            PriceSource::from_identifier(s.as_str())
+                .ok_or(format!("Unexpected price source: {}", s))
+        })
+        .collect()
+}
+
+fn fixed(price: f32) -> PriceOverride {
+    PriceOverride {
+        fixed: Some(price),
+        ..Default::default()
+    }
+}
+
+fn alias(symbol: &str) -> PriceOverride {
+    PriceOverride {
+        symbol: Some(symbol.to_string()),
+        ..Default::default()
+    }
+}
+
+fn default_overrides() -> HashMap<String, PriceOverride> {
+    let mut overrides = HashMap::new();
+    overrides.insert("BUSD".to_string(), fixed(1.0));
+    overrides.insert("USDT".to_string(), fixed(1.0));
+    overrides.insert("USDC".to_string(), fixed(1.0));
+    overrides.insert("DAI".to_string(), fixed(1.0)); // May the peg persist
+    overrides.insert("BTCB".to_string(), alias("BTC"));
+    overrides
+}
+
+// CoinGecko identifies coins by name, not by symbol
+fn default_coingecko_id(symbol: &str) -> Option<&str> {
+    match symbol {
+        "BTC" => Some("bitcoin"),
+        "ETH" => Some("ethereum"),
+        "BNB" => Some("binancecoin"),
+        "AVAX" => Some("avalanche-2"),
+        "MOVR" => Some("moonriver"),
+        "GLMR" => Some("moonbeam"),
+        "DOT" => Some("polkadot"),
+        "QI" => Some("benqi"),
+        "ALPACA" => Some("alpaca-finance"),
+        "XVS" => Some("venus"),
+        "CAKE" => Some("pancakeswap-token"),
+        _ => None,
+    }
+}
+
+fn kraken_symbol(symbol: &str) -> &str {
+    match symbol {
+        "BTC" => "XBT",
+        "DOGE" => "XDG",
+        s => s,
+    }
+}
+
+impl PriceOracle {
+    pub fn from_config(
+        config: Option<&PriceConfig>,
+        nodes: &Vec<EthereumNode>,
+    ) -> Result<Self, String> {
+        let default_config = PriceConfig::default();
+        let config = config.unwrap_or(&default_config);
+        let sources = match &config.sources {
+            Some(s) => parse_sources(s)?,
+            None => vec![
+                PriceSource::Binance,
+                PriceSource::Kraken,
+                PriceSource::CoinGecko,
+                PriceSource::Dex,
+            ],
+        };
+
+        let mut overrides = default_overrides();
+        for (symbol, o) in config.overrides.clone().unwrap_or_default() {
+            let pool = match (&o.chain, &o.pool) {
+                (Some(chain), Some(pool)) => Some((
+                    EthereumChain::from_identifier(chain.as_str())
+                        .ok_or(format!("Unexpected blockchain identifier: {}", chain))?,
+                    parse_address(pool)?,
+                )),
+                (None, None) => None,
+                _ => {
+                    return Err(format!(
+                        "Price override for {} needs both chain and pool",
+                        symbol
+                    ))
+                }
+            };
+            let sources = match &o.sources {
+                Some(s) => Some(parse_sources(s)?),
+                None => None,
+            };
+            overrides.insert(
+                symbol,
+                PriceOverride {
+                    sources: sources,
+                    symbol: o.symbol,
+                    fixed: o.fixed,
+                    coingecko_id: o.coingecko_id,
+                    pool: pool,
+                },
+            );
+        }
+
+        let url = |u: &Option<String>, default: &str| {
+            u.clone()
+                .unwrap_or(default.to_string())
+                .trim_end_matches('/')
+                .to_string()
+        };
+        Ok(Self {
+            sources: sources,
+            binance_url: url(&config.binance_url, BINANCE_URL),
+            kraken_url: url(&config.kraken_url, KRAKEN_URL),
+            coingecko_url: url(&config.coingecko_url, COINGECKO_URL),
+            overrides: overrides,
+            nodes: nodes
+                .iter()
+                .map(|n| EthereumNode {
+                    chain: n.chain.clone(),
+                    web3: n.web3.clone(),
+                })
+                .collect(),
+        })
+    }
+
+    // Asks the configured sources in order until one of them knows the price
+    pub async fn fetch_price(&self, symbol: &String) -> Result<f32, ApiError> {
+        let o = self.overrides.get(symbol).cloned().unwrap_or_default();
+        if let Some(price) = o.fixed {
+            return Ok(price);
+        }
+        let lookup_symbol = o.symbol.clone().unwrap_or(symbol.clone());
+        let sources = o.sources.clone().unwrap_or(self.sources.clone());
+
+        let mut errors = Vec::new();
+        for source in sources.iter() {
+            let price = match source {
+                PriceSource::Binance => self.fetch_from_binance(&lookup_symbol).await,
+                PriceSource::Kraken => self.fetch_from_kraken(&lookup_symbol).await,
+                PriceSource::CoinGecko => self.fetch_from_coingecko(&lookup_symbol, &o).await,
+                PriceSource::Dex => self.fetch_from_dex(&lookup_symbol, &o).await,
+            };
+            match price {
+                Ok(p) => return Ok(p),
+                Err(e) => errors.push(format!("{}: {}", source.to_identifier(), e.get_message())),
+            }
+        }
+        Err(ApiError::new(&format!(
+            "No price for {} ({})",
+            symbol,
+            errors.join("; ")
+        )))
+    }
+
+    async fn fetch_from_binance(&self, symbol: &String) -> Result<f32, ApiError> {
+        let result = reqwest::get(format!(
+            "{}/api/v3/ticker/price?symbol={}USDT",
+            self.binance_url, symbol
+        ))
+        .await?
+        .json::<Value>()
+        .await?;
+        match result.get("price").and_then(|p| p.as_str()) {
+            Some(price) => Ok(price.parse::<f32>()?),
+            None => Err(ApiError::new(&format!("Unexpected response {}", result))),
+        }
+    }
+
+    async fn fetch_from_kraken(&self, symbol: &String) -> Result<f32, ApiError> {
+        let client = kraken_client::Client::builder()
+            .base_url(&self.kraken_url)
+            .build();
+        let tickers = client
+            .get_tickers(&format!("{}USD", kraken_symbol(symbol)))
+            .send()
+            .await?;
+        // Kraken answers with its own pair name, e.g. XXBTZUSD for XBTUSD
+        match tickers.values().next().and_then(|t| t.c.first()) {
+            Some(price) => Ok(price.parse::<f32>()?),
+            None => Err(ApiError::new(&"Empty ticker".to_string())),
+        }
+    }
+
+    async fn fetch_from_coingecko(
+        &self,
+        symbol: &String,
+        o: &PriceOverride,
+    ) -> Result<f32, ApiError> {
+        let id = match o.coingecko_id.as_deref().or(default_coingecko_id(symbol)) {
+            Some(id) => id,
+            None => return Err(ApiError::new(&"No coingecko-id configured".to_string())),
+        };
+        let result = reqwest::get(format!(
+            "{}/api/v3/simple/price?ids={}&vs_currencies=usd",
+            self.coingecko_url, id
+        ))
+        .await?
+        .json::<Value>()
+        .await?;
+        match result
+            .get(id)
+            .and_then(|p| p.get("usd"))
+            .and_then(|p| p.as_f64())
+        {
+            Some(price) => Ok(price as f32),
+            None => Err(ApiError::new(&format!("Unexpected response {}", result))),
+        }
+    }
+
+    async fn fetch_from_dex(&self, symbol: &String, o: &PriceOverride) -> Result<f32, ApiError> {
+        let (chain, pool) = match &o.pool {
+            Some(p) => p,
+            None => return Err(ApiError::new(&"No pool configured".to_string())),
+        };
+        let mut errors = Vec::new();
+        for node in self.nodes.iter().filter(|n| n.chain == *chain) {
+            match get_pair_price(&node.web3, pool, symbol).await {
+                Ok((price, quote)) => return Ok(price * get_quote_price(quote).await?),
+                Err(e) => errors.push(e.get_message()),
+            }
+        }
+        if errors.is_empty() {
+            errors.push(format!(
+                "No node configured for blockchain {}",
+                chain.to_str()
+            ));
+        }
+        Err(ApiError::new(&errors.join("; ")))
+    }
+}
+
+static PRICE_CACHE: OnceLock<TtlCache<String, f32>> = OnceLock::new();
+static PRICE_ORACLE: OnceLock<PriceOracle> = OnceLock::new();
+
+pub fn default_price_cache_settings() -> CacheSettings {
+    CacheSettings::new(Duration::from_secs(60), Duration::from_secs(600))
+}
+
+// Has to be called before the first price lookup to take effect
+pub fn configure_price_cache(settings: CacheSettings) {
+    let _ = PRICE_CACHE.set(TtlCache::new(settings));
+}
+
+// Has to be called before the first price lookup to take effect
+pub fn configure_price_oracle(oracle: PriceOracle) {
+    let _ = PRICE_ORACLE.set(oracle);
+}
+
+fn price_cache() -> &'static TtlCache<String, f32> {
+    PRICE_CACHE.get_or_init(|| TtlCache::new(default_price_cache_settings()))
+}
+
+fn price_oracle() -> &'static PriceOracle {
+    PRICE_ORACLE.get_or_init(|| PriceOracle::from_config(None, &Vec::new()).unwrap())
+}
+
+pub fn get_price_cache_stats() -> CacheStats {
+    price_cache().get_stats()
+}
 
 pub async fn get_token_price(symbol: &String) -> Result<f32, ApiError> {
-    let mut symbol = symbol.clone();
-
-    // Special symbol handling
-    match symbol.as_str() {
-        "BUSD" => return Ok(1.0),
-        "DAI" => return Ok(1.0), // May the peg persist
-        "BTCB" => symbol = "BTC".to_string(),
-        _ => (),
-    }
-
-    let result = get(format!(
-        "https://api.binance.com/api/v1/ticker/24hr?symbol={}BUSD",
-        symbol
-    ))
-    .await?
-    .json::<Value>()
-    .await?;
-    let price_string = result.get("lastPrice");
-    if price_string.is_none() {
-        return Err(ApiError::new(&format!(
-            "Invalid JSON format when looking up price for {}",
-            symbol
-        )));
-    }
-    let price_string = price_string.unwrap().as_str().unwrap();
-
-    Ok(price_string.parse::<f32>()?)
+    let symbol = symbol.clone();
+    price_cache()
+        .get_or_revalidate(symbol.clone(), || fetch_token_price(symbol))
+        .await
+}
+
+// Dex prices are in units of the other pool token, which needs a price
+// lookup itself. Boxed, as the lookup may end up here again.
+fn get_quote_price(symbol: String) -> Pin<Box<dyn Future<Output = Result<f32, ApiError>> + Send>> {
+    Box::pin(async move { get_token_price(&symbol).await })
+}
+
+async fn fetch_token_price(symbol: String) -> Result<f32, ApiError> {
+    price_oracle().fetch_price(&symbol).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // Minimal HTTP server answering every request whose path
+    // starts with one of the prefixes with the given status and body
+    async fn mock_server(routes: Vec<(&'static str, u16, &'static str)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buffer = vec![0; 4096];
+                let n = socket.read(&mut buffer).await.unwrap();
+                let request = String::from_utf8_lossy(&buffer[..n]).to_string();
+                let path = request.split(' ').nth(1).unwrap_or("").to_string();
+                let (status, body) = routes
+                    .iter()
+                    .find(|r| path.starts_with(r.0))
+                    .map(|r| (r.1, r.2))
+                    .unwrap_or((404, "{}"));
+                let response = format!(
+                    "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    fn oracle(
+        url: &String,
+        overrides: Option<HashMap<String, PriceOverrideConfig>>,
+    ) -> PriceOracle {
+        let config = PriceConfig {
+            sources: None,
+            binance_url: Some(url.clone()),
+            kraken_url: Some(url.clone()),
+            coingecko_url: Some(url.clone()),
+            overrides: overrides,
+        };
+        PriceOracle::from_config(Some(&config), &Vec::new()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_fallback() {
+        let url = mock_server(vec![
+            ("/api/v3/ticker/price?symbol=BTCUSDT", 200, r#"{"symbol":"BTCUSDT","price":"40000.5"}"#),
+            ("/api/v3/ticker/price", 400, r#"{"code":-1121,"msg":"Invalid symbol."}"#),
+            ("/0/public/Ticker?pair=QIUSD", 200, r#"{"error":["EQuery:Unknown asset pair"]}"#),
+            ("/0/public/Ticker?pair=DOTUSD", 200, r#"{"error":[],"result":{"DOTUSD":{"a":["7.1","1","1"],"b":["7.0","1","1"],"c":["7.05","2"],"v":["1","1"],"p":["7","7"],"t":[1,1],"l":["7","7"],"h":["7","7"],"o":"7"}}}"#),
+            ("/api/v3/simple/price?ids=benqi", 200, r#"{"benqi":{"usd":0.05}}"#),
+            ("/api/v3/simple/price?ids=special", 200, r#"{"special":{"usd":3.0}}"#),
+        ])
+        .await;
+
+        let mut overrides = HashMap::new();
+        overrides.insert(
+            "BTC".to_string(),
+            PriceOverrideConfig {
+                sources: Some(vec!["coingecko".to_string(), "binance".to_string()]),
+                ..Default::default()
+            },
+        );
+        overrides.insert(
+            "XYZ".to_string(),
+            PriceOverrideConfig {
+                coingecko_id: Some("special".to_string()),
+                ..Default::default()
+            },
+        );
+        let oracle = oracle(&url, Some(overrides));
+
+        let price = |s: &str| s.to_string();
+        assert_eq!(oracle.fetch_price(&price("BTCB")).await.unwrap(), 40000.5);
+        assert_eq!(oracle.fetch_price(&price("BTC")).await.unwrap(), 40000.5);
+        assert_eq!(oracle.fetch_price(&price("DOT")).await.unwrap(), 7.05);
+        assert_eq!(oracle.fetch_price(&price("QI")).await.unwrap(), 0.05);
+        assert_eq!(oracle.fetch_price(&price("XYZ")).await.unwrap(), 3.0);
+        assert_eq!(oracle.fetch_price(&price("DAI")).await.unwrap(), 1.0);
+
+        let error = oracle.fetch_price(&price("NOPE")).await.unwrap_err();
+        for source in ["binance", "kraken", "coingecko", "dex"] {
+            assert!(error.get_message().contains(source));
+        }
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let config = PriceConfig {
+            sources: Some(vec!["binance".to_string(), "etoro".to_string()]),
+            ..Default::default()
+        };
+        assert!(PriceOracle::from_config(Some(&config), &Vec::new()).is_err());
+    }
 }
@@ -0,0 +1,77 @@
+// Calendar dates as YYYY-MM-DD, e.g. 2022-01-13, always UTC
+
+// Year, month and day of a date, None unless the day exists
+pub fn parse_date(date: &str) -> Option<(i64, i64, i64)> {
+    let mut parts = date.split('-');
+    let mut part = |digits: usize| {
+        let p = parts.next()?;
+        if p.len() != digits || !p.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        p.parse::<i64>().ok()
+    };
+    let (y, m, d) = (part(4)?, part(2)?, part(2)?);
+    if parts.next().is_some() {
+        return None;
+    }
+    let leap = y % 4 == 0 && (y % 100 != 0 || y % 400 == 0);
+    let days_in_month = match m {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return None,
+    };
+    if d < 1 || d > days_in_month {
+        return None;
+    }
+    Some((y, m, d))
+}
+
+// Days since 1970-01-01, http://howardhinnant.github.io/date_algorithms.html
+pub fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+// The date of days since 1970-01-01, the inverse of days_from_civil
+pub fn format_date(days: i64) -> String {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let d = day_of_year - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = year_of_era + era * 400 + if m <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(parse_date("2024-02-29"), Some((2024, 2, 29)));
+        assert_eq!(parse_date("2023-02-29"), None);
+        assert_eq!(parse_date("2022-13-45"), None);
+        assert_eq!(parse_date("2022-1-13"), None);
+        assert_eq!(parse_date("2022-01-13T10:20:30Z"), None);
+
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2024, 2, 29), 19782);
+        assert_eq!(format_date(19782), "2024-02-29");
+        assert_eq!(format_date(-1), "1969-12-31");
+        for days in [0, 59, 365, 10956, 19417] {
+            let (y, m, d) = parse_date(&format_date(days)).unwrap();
+            assert_eq!(days_from_civil(y, m, d), days);
+        }
+    }
+}
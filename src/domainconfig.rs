@@ -1,15 +1,26 @@
-use super::account::{Account, EthereumAccount, KrakenAccount, NordigenAccount, ScalableAccount};
 use super::blockchain::EthDefiToken;
-use super::config::Configuration;
+use super::cache::{default_cache_settings, CacheConfig, CacheSettings, CacheStats};
+use super::config::{expand_home, Configuration};
+use super::cryptoprice::{
+    configure_price_cache, configure_price_oracle, default_price_cache_settings,
+    get_price_cache_stats, PriceOracle,
+};
+use super::currency::FiatCurrency;
 use super::ethereum::{EthereumChain, EthereumNode};
+use super::fx::{configure_fx, default_fx_cache_settings, get_fx_cache_stats, parse_currency};
+use super::history::HistoryStore;
+use super::provider::{AccountProvider, ProviderRegistry};
 use hex::decode_to_slice;
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use web3::types::{Address, H160};
 
 pub struct Fund {
     pub name: String,
     pub icon: Option<String>,
-    pub accounts: Vec<Account>,
+    pub accounts: Vec<Arc<dyn AccountProvider>>,
     pub target_size: Option<f32>,
 }
 
@@ -18,104 +29,55 @@ pub struct DomainConfig {
     pub smart_contracts: Vec<(EthereumChain, EthDefiToken, Address)>,
     pub eth_nodes: Vec<EthereumNode>,
     pub client_whitelist: Option<Vec<String>>,
-    pub nordigen_cache_hours: Option<u64>,
+    pub cache_config: HashMap<String, CacheConfig>,
+    // The providers keep their own state, like caches
+    pub providers: ProviderRegistry,
+    pub max_concurrent_requests: usize,
+    pub refresh_minutes: HashMap<String, u64>,
+    pub history: Option<HistoryStore>,
+    pub history_interval: Duration,
+    // Warn this long before credentials expire
+    pub expiry_warning: Duration,
+    // Currency balances are reported in, unless requested otherwise
+    pub base_currency: FiatCurrency,
     pub port: u16,
     pub listen_address: String,
 }
 
-fn hex_to_address(hex: &String) -> Address {
+pub fn parse_address(hex: &String) -> Result<Address, String> {
     let mut slice: [u8; 20] = [0; 20];
-    decode_to_slice(hex, &mut slice as &mut [u8]).unwrap();
-    H160(slice)
+    decode_to_slice(hex.trim_start_matches("0x"), &mut slice as &mut [u8])
+        .map_err(|e| format!("Invalid address {}: {}", hex, e))?;
+    Ok(H160(slice))
+}
+
+fn hex_to_address(hex: &String) -> Address {
+    parse_address(hex).unwrap()
 }
 
 fn blockchain_identifier_to_enum(identifier: &str) -> EthereumChain {
-    match identifier {
-        "bsc" => EthereumChain::BinanceSmartChain,
-        "avalanche-c" => EthereumChain::AvalancheC,
-        "ethereum" => EthereumChain::Ethereum,
-        "moonriver" => EthereumChain::Moonriver,
-        "moonbeam" => EthereumChain::Moonbeam,
-        e => panic!("Unexpected blockchain identifier: {}", e),
+    match EthereumChain::from_identifier(identifier) {
+        Some(chain) => chain,
+        None => panic!("Unexpected blockchain identifier: {}", identifier),
     }
 }
 
 impl DomainConfig {
     pub fn from_config(config: Configuration) -> Self {
+        let registry = ProviderRegistry::default();
+        registry.configure(&config);
         let mut accounts = HashMap::new();
         for (name, account_config) in config.accounts.iter() {
-            let account = match account_config.kind.as_str() {
-                "bsc" => Account::Ethereum(EthereumAccount {
-                    name: name.clone(),
-                    chain: EthereumChain::BinanceSmartChain,
-                    wallet_address: hex_to_address(
-                        &account_config
-                            .address
-                            .clone()
-                            .expect("Account of type bsc requires address"),
-                    ),
-                }),
-                "avalanche-c" => Account::Ethereum(EthereumAccount {
-                    name: name.clone(),
-                    chain: EthereumChain::AvalancheC,
-                    wallet_address: hex_to_address(
-                        &account_config
-                            .address
-                            .clone()
-                            .expect("Account of type bsc requires address"),
-                    ),
-                }),
-                "kraken" => Account::Kraken(KrakenAccount {
-                    name: name.clone(),
-                    api_key: account_config
-                        .api_key
-                        .clone()
-                        .expect("Kraken requires api-key"),
-                    api_secret: account_config
-                        .api_secret
-                        .clone()
-                        .expect("Kraken requires api-secret"),
-                }),
-                "moonbeam" => Account::Ethereum(EthereumAccount {
-                    name: name.clone(),
-                    chain: EthereumChain::Moonbeam,
-                    wallet_address: hex_to_address(
-                        &account_config
-                            .address
-                            .clone()
-                            .expect("Account of type moonbeam requires address"),
-                    ),
-                }),
-                "nordigen" => Account::Nordigen(NordigenAccount {
-                    name: name.clone(),
-                    refresh_token: account_config
-                        .refresh_token
-                        .clone()
-                        .expect("A nordigen account requires refresh-token"),
-                    account_id: account_config
-                        .account_id
-                        .clone()
-                        .expect("A nordigen account requires account-id"),
-                }),
-                "scalable" => Account::Scalable(ScalableAccount {
-                    name: name.clone(),
-                    email: account_config
-                        .email
-                        .clone()
-                        .expect("Scalable account requires email"),
-                    password: account_config
-                        .password
-                        .clone()
-                        .expect("Scalable account requires password"),
-                }),
-                x => panic!("Invalid account type: {}", x),
+            let account = match registry.create(name, account_config) {
+                Ok(account) => account,
+                Err(e) => panic!("{}", e),
             };
             accounts.insert(name.clone(), account);
         }
 
         let mut funds = Vec::new();
         for config_fund in config.funds.iter() {
-            let mut fund_accounts: Vec<Account> = Vec::new();
+            let mut fund_accounts: Vec<Arc<dyn AccountProvider>> = Vec::new();
             for account_identifier in config_fund.accounts.iter() {
                 fund_accounts.push(
                     accounts
@@ -140,7 +102,7 @@ impl DomainConfig {
             let chain = blockchain_identifier_to_enum(chain_identifier.as_str());
             for (defi_platform_name, contract_address_strings) in defi_platforms.iter() {
                 let defi_platform = match defi_platform_name.as_str() {
-                    "bep20" => EthDefiToken::Bep20,
+                    "bep20" | "erc20" => EthDefiToken::Bep20,
                     "alpaca" => EthDefiToken::Alpaca,
                     "venus" => EthDefiToken::Venus,
                     "benqi" => EthDefiToken::Benqi,
@@ -168,18 +130,84 @@ impl DomainConfig {
             })
             .collect::<Vec<EthereumNode>>();
 
+        configure_price_cache(config.get_cache_settings("prices", default_price_cache_settings()));
+        configure_fx(
+            config.fx.as_ref(),
+            config.get_cache_settings("fx", default_fx_cache_settings()),
+        );
+        let cache_config = config.cache.unwrap_or_default();
+        match PriceOracle::from_config(config.prices.as_ref(), &nodes) {
+            Ok(oracle) => configure_price_oracle(oracle),
+            Err(e) => panic!("Invalid price config: {}", e),
+        }
+
         Self {
             funds: funds,
             smart_contracts: smart_contracts,
             eth_nodes: nodes,
             client_whitelist: config.clients,
             port: config.port,
-            nordigen_cache_hours: config.nordigen_cache_hours,
+            cache_config: cache_config,
+            providers: registry,
+            max_concurrent_requests: config.max_concurrent_requests.unwrap_or(8).max(1),
+            refresh_minutes: config.refresh_minutes.unwrap_or_default(),
+            history: config
+                .history_file
+                .map(|path| HistoryStore::new(PathBuf::from(expand_home(&path)))),
+            // Default to one snapshot per hour
+            history_interval: Duration::from_secs(
+                config.history_interval_minutes.unwrap_or(60).max(1) * 60,
+            ),
+            expiry_warning: Duration::from_secs(config.expiry_warning_days.unwrap_or(14) * 86400),
+            base_currency: match parse_currency(&config.base_currency.unwrap_or("USD".to_string()))
+            {
+                Ok(c) => c,
+                Err(e) => panic!("{}", e.get_message()),
+            },
             listen_address: config.listen_address,
         }
     }
 }
 
+impl DomainConfig {
+    // Every account which is part of at least one fund, once
+    pub fn get_fund_accounts(&self) -> Vec<Arc<dyn AccountProvider>> {
+        let mut result: Vec<Arc<dyn AccountProvider>> = Vec::new();
+        for fund in self.funds.iter() {
+            for account in fund.accounts.iter() {
+                if !result.iter().any(|a| a.get_name() == account.get_name()) {
+                    result.push(account.clone());
+                }
+            }
+        }
+        result
+    }
+
+    pub fn get_cache_settings(&self, kind: &str) -> CacheSettings {
+        CacheSettings::from_config(self.cache_config.get(kind), default_cache_settings())
+    }
+
+    pub fn get_cache_stats(&self) -> Vec<(String, CacheStats)> {
+        let mut stats = vec![
+            ("prices".to_string(), get_price_cache_stats()),
+            ("fx".to_string(), get_fx_cache_stats()),
+        ];
+        stats.append(&mut self.providers.get_cache_stats());
+        stats
+    }
+
+    // Per account kind, falling back to the "default" entry or ten minutes
+    pub fn get_refresh_interval(&self, kind: &String) -> Duration {
+        let minutes = self
+            .refresh_minutes
+            .get(kind)
+            .or_else(|| self.refresh_minutes.get("default"))
+            .cloned()
+            .unwrap_or(10);
+        Duration::from_secs(minutes.max(1) * 60)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -6,12 +6,57 @@ use std::io::Cursor;
 #[derive(serde::Serialize, Debug)]
 pub struct ApiError {
     error_message: String,
+    // The source answered, but the account is gone (e.g. a disconnected bank)
+    #[serde(skip)]
+    unavailable: bool,
+    // Waiting for the user, e.g. to confirm a login in an app
+    #[serde(skip)]
+    pending: bool,
 }
 
 impl ApiError {
     pub fn new(error_message: &String) -> ApiError {
         ApiError {
             error_message: error_message.clone(),
+            unavailable: false,
+            pending: false,
+        }
+    }
+
+    pub fn unavailable(error_message: &String) -> ApiError {
+        ApiError {
+            error_message: error_message.clone(),
+            unavailable: true,
+            pending: false,
+        }
+    }
+
+    pub fn pending(error_message: &String) -> ApiError {
+        ApiError {
+            error_message: error_message.clone(),
+            unavailable: false,
+            pending: true,
+        }
+    }
+
+    pub fn is_unavailable(&self) -> bool {
+        self.unavailable
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    pub fn get_message(&self) -> String {
+        self.error_message.clone()
+    }
+
+    // Tell which account an error originated from
+    pub fn for_account(self, name: &String, kind: &String) -> Self {
+        Self {
+            error_message: format!("Account {} ({}): {}", name, kind, self.error_message),
+            unavailable: self.unavailable,
+            pending: self.pending,
         }
     }
 }
@@ -19,9 +64,14 @@ impl ApiError {
 #[rocket::async_trait]
 impl<'r> Responder<'r, 'static> for ApiError {
     fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let status = if self.unavailable || self.pending {
+            Status::ServiceUnavailable
+        } else {
+            Status::BadRequest
+        };
         let body: String = serde_json::to_string(&self).unwrap();
         Response::build()
-            .status(Status::BadRequest)
+            .status(status)
             .header(ContentType::JSON)
             .sized_body(body.len(), Cursor::new(body))
             .ok()
@@ -32,6 +82,8 @@ impl From<web3::Error> for ApiError {
     fn from(err: web3::Error) -> Self {
         Self {
             error_message: format!("{:?}", err),
+            unavailable: false,
+            pending: false,
         }
     }
 }
@@ -40,6 +92,8 @@ impl From<web3::contract::Error> for ApiError {
     fn from(err: web3::contract::Error) -> Self {
         Self {
             error_message: format!("Smart contract error: {:?}", err),
+            unavailable: false,
+            pending: false,
         }
     }
 }
@@ -48,22 +102,38 @@ impl From<reqwest::Error> for ApiError {
     fn from(err: reqwest::Error) -> Self {
         Self {
             error_message: format!("HTTP request error: {:?}", err),
+            unavailable: false,
+            pending: false,
         }
     }
 }
 
-impl From<std::num::ParseFloatError> for ApiError {
-    fn from(err: std::num::ParseFloatError) -> Self {
+impl From<std::io::Error> for ApiError {
+    fn from(err: std::io::Error) -> Self {
         Self {
-            error_message: format!("Error parsing string: {:?}", err),
+            error_message: format!("IO error: {:?}", err),
+            unavailable: false,
+            pending: false,
         }
     }
 }
 
-impl From<kraken_client::error::Error> for ApiError {
-    fn from(err: kraken_client::error::Error) -> Self {
+impl From<serde_json::Error> for ApiError {
+    fn from(err: serde_json::Error) -> Self {
         Self {
-            error_message: format!("Kraken API Error: {:?}", err),
+            error_message: format!("JSON error: {:?}", err),
+            unavailable: false,
+            pending: false,
+        }
+    }
+}
+
+impl From<std::num::ParseFloatError> for ApiError {
+    fn from(err: std::num::ParseFloatError) -> Self {
+        Self {
+            error_message: format!("Error parsing string: {:?}", err),
+            unavailable: false,
+            pending: false,
         }
     }
 }
@@ -11,6 +11,25 @@ pub enum EthereumChain {
 }
 
 impl EthereumChain {
+    pub const ALL: [EthereumChain; 5] = [
+        Self::BinanceSmartChain,
+        Self::AvalancheC,
+        Self::Ethereum,
+        Self::Moonriver,
+        Self::Moonbeam,
+    ];
+
+    // Identifiers as used in the config file, also the account kinds
+    pub fn get_identifiers() -> Vec<&'static str> {
+        Self::ALL.iter().map(|c| c.to_identifier()).collect()
+    }
+
+    pub fn from_identifier(identifier: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|c| c.to_identifier() == identifier)
+            .cloned()
+    }
     pub fn _from_str(s: &str) -> Self {
         match s.to_lowercase().as_str() {
             "avac" => Self::AvalancheC,
@@ -27,6 +46,15 @@ impl EthereumChain {
             _ => panic!("Invalid chain identifier!"),
         }
     }
+    pub fn to_identifier(&self) -> &'static str {
+        match *self {
+            Self::AvalancheC => "avalanche-c",
+            Self::BinanceSmartChain => "bsc",
+            Self::Ethereum => "ethereum",
+            Self::Moonriver => "moonriver",
+            Self::Moonbeam => "moonbeam",
+        }
+    }
     pub fn to_str(&self) -> &str {
         match *self {
             Self::AvalancheC => "Avalanche-C",
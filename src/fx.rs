@@ -0,0 +1,192 @@
+use super::cache::{CacheSettings, CacheStats, TtlCache};
+use super::cryptoprice::get_token_price;
+use super::currency::FiatCurrency;
+use super::date::{days_from_civil, format_date, parse_date};
+use super::error::ApiError;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+// All prices are looked up in USD, every conversion into
+// another currency goes through here.
+
+const FX_URL: &str = "https://api.frankfurter.app";
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct FxConfig {
+    // Frankfurter compatible API, answering /latest?from=USD
+    pub url: Option<String>,
+}
+
+static FX_URL_CONFIG: OnceLock<String> = OnceLock::new();
+static FX_CACHE: OnceLock<TtlCache<String, HashMap<String, f32>>> = OnceLock::new();
+
+pub fn default_fx_cache_settings() -> CacheSettings {
+    CacheSettings::new(Duration::from_secs(3600), Duration::from_secs(24 * 3600))
+}
+
+// Has to be called before the first conversion to take effect
+pub fn configure_fx(config: Option<&FxConfig>, settings: CacheSettings) {
+    let url = config
+        .and_then(|c| c.url.clone())
+        .unwrap_or(FX_URL.to_string());
+    let _ = FX_URL_CONFIG.set(url.trim_end_matches('/').to_string());
+    let _ = FX_CACHE.set(TtlCache::new(settings));
+}
+
+fn fx_cache() -> &'static TtlCache<String, HashMap<String, f32>> {
+    FX_CACHE.get_or_init(|| TtlCache::new(default_fx_cache_settings()))
+}
+
+pub fn get_fx_cache_stats() -> CacheStats {
+    fx_cache().get_stats()
+}
+
+pub fn parse_currency(code: &String) -> Result<FiatCurrency, ApiError> {
+    FiatCurrency::from_code(code)
+}
+
+// Units of each currency per USD
+fn parse_rates(result: &Value) -> Result<HashMap<String, f32>, ApiError> {
+    let rates = match result.get("rates").and_then(|r| r.as_object()) {
+        Some(r) => r,
+        None => {
+            return Err(ApiError::new(&format!(
+                "Unexpected exchange rate response {}",
+                result
+            )))
+        }
+    };
+    let mut map: HashMap<String, f32> = rates
+        .iter()
+        .filter_map(|(code, rate)| rate.as_f64().map(|r| (code.to_uppercase(), r as f32)))
+        .filter(|(_, rate)| *rate > 0.)
+        .collect();
+    map.insert("USD".to_string(), 1.0);
+    Ok(map)
+}
+
+async fn fetch_rates() -> Result<HashMap<String, f32>, ApiError> {
+    let url = FX_URL_CONFIG.get_or_init(|| FX_URL.to_string());
+    let result = reqwest::get(format!("{}/latest?from=USD", url))
+        .await?
+        .json::<Value>()
+        .await?;
+    parse_rates(&result)
+}
+
+// Value of one unit of the currency in USD. Uses the FX source and
+// falls back to the token price sources for currencies it does not know.
+async fn get_usd_rate(currency: &FiatCurrency) -> Result<f32, ApiError> {
+    if *currency == FiatCurrency::USD {
+        return Ok(1.0);
+    }
+    let fx_error = match fx_cache()
+        .get_or_revalidate("USD".to_string(), fetch_rates)
+        .await
+    {
+        Ok(rates) => match rates.get(currency.get_code()) {
+            Some(rate) => return Ok(1. / rate),
+            None => "not listed".to_string(),
+        },
+        Err(e) => e.get_message(),
+    };
+    get_token_price(&currency.to_string()).await.map_err(|e| {
+        ApiError::new(&format!(
+            "No exchange rate for {} (fx: {}; {})",
+            currency.get_code(),
+            fx_error,
+            e.get_message()
+        ))
+    })
+}
+
+// Value of one unit of the currency in USD per day (days since 1970-01-01),
+// of the time series in a response of /<date>..?from=USD&to=<code>
+fn parse_rate_history(result: &Value, code: &str) -> Result<Vec<(i64, f64)>, ApiError> {
+    let rates = match result.get("rates").and_then(|r| r.as_object()) {
+        Some(r) => r,
+        None => {
+            return Err(ApiError::new(&format!(
+                "Unexpected exchange rate history response {}",
+                result
+            )))
+        }
+    };
+    Ok(rates
+        .iter()
+        .filter_map(|(date, rates)| {
+            let (y, m, d) = parse_date(date)?;
+            let units = rates.get(code)?.as_f64().filter(|r| *r > 0.)?;
+            Some((days_from_civil(y, m, d), 1. / units))
+        })
+        .collect())
+}
+
+// Daily USD values of the currency since the day, only of working days.
+// Not cached, the callers keep what they need.
+pub async fn get_usd_rate_history(
+    currency: &FiatCurrency,
+    since: i64,
+) -> Result<Vec<(i64, f64)>, ApiError> {
+    let url = FX_URL_CONFIG.get_or_init(|| FX_URL.to_string());
+    let result = reqwest::get(format!(
+        "{}/{}..?from=USD&to={}",
+        url,
+        format_date(since),
+        currency.get_code()
+    ))
+    .await?
+    .json::<Value>()
+    .await?;
+    parse_rate_history(&result, currency.get_code())
+}
+
+// Multiply an amount in `from` by this to get it in `to`
+pub async fn get_fx_rate(from: &FiatCurrency, to: &FiatCurrency) -> Result<f32, ApiError> {
+    if from == to {
+        return Ok(1.0);
+    }
+    Ok(get_usd_rate(from).await? / get_usd_rate(to).await?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_rates() {
+        let rates = parse_rates(&json!({
+            "amount": 1.0,
+            "base": "USD",
+            "date": "2022-01-14",
+            "rates": {"EUR": 0.8, "CHF": 0.91, "BAD": "x"}
+        }))
+        .unwrap();
+        assert_eq!(rates.get("EUR"), Some(&0.8));
+        assert_eq!(rates.get("USD"), Some(&1.0));
+        assert_eq!(rates.get("BAD"), None);
+        assert!(parse_rates(&json!({"message": "not found"})).is_err());
+    }
+
+    #[test]
+    fn test_parse_rate_history() {
+        let mut rates = parse_rate_history(
+            &json!({
+                "amount": 1.0,
+                "base": "USD",
+                "start_date": "2022-01-14",
+                "end_date": "2022-01-17",
+                "rates": {"2022-01-14": {"EUR": 0.8}, "2022-01-17": {"EUR": 0.5}, "bad": {"EUR": 1.0}}
+            }),
+            "EUR",
+        )
+        .unwrap();
+        rates.sort_by_key(|r| r.0);
+        assert_eq!(rates, vec![(19006, 1.25), (19009, 2.0)]);
+        assert!(parse_rate_history(&json!({"message": "not found"}), "EUR").is_err());
+    }
+}
@@ -0,0 +1,233 @@
+use super::api::FundDto;
+use super::error::ApiError;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Snapshots are stored as an append-only file,
+// one JSON document (all funds at one point in time) per line.
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AssetSnapshot {
+    pub name: String,
+    pub description: String,
+    pub units: f32,
+    pub unit_price: f32,
+    pub value: f32,
+    pub nominal_growth: f32,
+    pub real_growth: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FundSnapshot {
+    pub name: String,
+    pub balance: f32,
+    pub nominal_yearly_growth: f32,
+    pub real_yearly_growth: f32,
+    pub assets: Vec<AssetSnapshot>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    time: u64,
+    // Snapshots recorded before currencies were configurable are in USD
+    #[serde(default = "default_currency")]
+    currency: String,
+    funds: Vec<FundSnapshot>,
+}
+
+#[derive(Serialize)]
+pub struct HistoryPointDto {
+    pub time: u64,
+    pub currency: String,
+    pub balance: f32,
+    pub nominal_yearly_growth: f32,
+    pub real_yearly_growth: f32,
+    pub assets: Vec<AssetSnapshot>,
+}
+
+#[derive(Serialize)]
+pub struct FundHistoryDto {
+    pub fund: String,
+    pub points: Vec<HistoryPointDto>,
+}
+
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl FundSnapshot {
+    pub fn from_fund_dto(fund: &FundDto) -> Self {
+        Self {
+            name: fund.name.clone(),
+            balance: fund.balance,
+            nominal_yearly_growth: fund.nominal_yearly_growth,
+            real_yearly_growth: fund.real_yearly_growth,
+            assets: fund
+                .assets
+                .iter()
+                .map(|a| AssetSnapshot {
+                    name: a.name.clone(),
+                    description: a.description.clone(),
+                    units: a.units,
+                    unit_price: a.unit_price,
+                    value: a.units * a.unit_price,
+                    nominal_growth: a.nominal_growth,
+                    real_growth: a.real_growth,
+                })
+                .collect(),
+        }
+    }
+}
+
+pub struct HistoryStore {
+    path: PathBuf,
+    write_lock: Mutex<()>,
+}
+
+impl HistoryStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn append(
+        &self,
+        time: u64,
+        currency: &str,
+        funds: Vec<FundSnapshot>,
+    ) -> Result<(), ApiError> {
+        let mut line = serde_json::to_string(&Snapshot {
+            time,
+            currency: currency.to_string(),
+            funds,
+        })?;
+        line.push('\n');
+
+        let _guard = self.write_lock.lock().unwrap();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    // All snapshots with from <= time <= to, grouped by fund
+    pub fn query(
+        &self,
+        fund: Option<&str>,
+        from: Option<u64>,
+        to: Option<u64>,
+    ) -> Result<Vec<FundHistoryDto>, ApiError> {
+        let file = match File::open(&self.path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut result: Vec<FundHistoryDto> = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let snapshot: Snapshot = match serde_json::from_str(&line) {
+                Ok(s) => s,
+                // A crash while writing may leave a truncated last line
+                Err(_) => continue,
+            };
+            if from.is_some_and(|from| snapshot.time < from)
+                || to.is_some_and(|to| snapshot.time > to)
+            {
+                continue;
+            }
+            for fund_snapshot in snapshot.funds {
+                if fund.is_some_and(|f| f != fund_snapshot.name) {
+                    continue;
+                }
+                let point = HistoryPointDto {
+                    time: snapshot.time,
+                    currency: snapshot.currency.clone(),
+                    balance: fund_snapshot.balance,
+                    nominal_yearly_growth: fund_snapshot.nominal_yearly_growth,
+                    real_yearly_growth: fund_snapshot.real_yearly_growth,
+                    assets: fund_snapshot.assets,
+                };
+                match result.iter_mut().find(|h| h.fund == fund_snapshot.name) {
+                    Some(history) => history.points.push(point),
+                    None => result.push(FundHistoryDto {
+                        fund: fund_snapshot.name,
+                        points: vec![point],
+                    }),
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fund(name: &str, balance: f32) -> FundSnapshot {
+        FundSnapshot {
+            name: name.to_string(),
+            balance,
+            nominal_yearly_growth: 0.1,
+            real_yearly_growth: 0.05,
+            assets: vec![AssetSnapshot {
+                name: "BTC".to_string(),
+                description: "Kraken".to_string(),
+                units: 1.,
+                unit_price: balance,
+                value: balance,
+                nominal_growth: 0.1,
+                real_growth: 0.05,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_append_and_query() {
+        let path = std::env::temp_dir().join(format!("get-rich-slow-history-{}.jsonl", unix_now()));
+        let store = HistoryStore::new(path.clone());
+        let usd = "USD".to_string();
+        store
+            .append(100, &usd, vec![fund("A", 1.), fund("B", 2.)])
+            .unwrap();
+        store
+            .append(200, &usd, vec![fund("A", 3.), fund("B", 4.)])
+            .unwrap();
+        store.append(300, "EUR", vec![fund("A", 5.)]).unwrap();
+
+        let all = store.query(None, None, None).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].points.len(), 3);
+        assert_eq!(all[1].points.len(), 2);
+
+        let a = store.query(Some("A"), Some(150), Some(300)).unwrap();
+        assert_eq!(a.len(), 1);
+        assert_eq!(
+            a[0].points.iter().map(|p| p.time).collect::<Vec<u64>>(),
+            vec![200, 300]
+        );
+        assert_eq!(a[0].points[1].balance, 5.);
+        assert_eq!(a[0].points[1].currency, "EUR");
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
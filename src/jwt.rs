@@ -0,0 +1,95 @@
+use super::error::ApiError;
+use serde_json::Value;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Read-only view of a JSON Web Token. The signature is not verified,
+// the claims are only used to know when to get a new token.
+#[derive(Clone)]
+pub struct Jwt {
+    claims: Value,
+}
+
+impl Jwt {
+    pub fn parse(token: &String) -> Result<Self, ApiError> {
+        let payload = match token.split('.').nth(1) {
+            Some(p) if token.split('.').count() == 3 => p,
+            _ => {
+                return Err(ApiError::new(
+                    &"Invalid JWT: expected three parts".to_string(),
+                ))
+            }
+        };
+        // Some issuers (e.g. Scalable Capital) use the standard alphabet and padding
+        let payload = payload
+            .trim_end_matches('=')
+            .replace('+', "-")
+            .replace('/', "_");
+        let decoded = base64::decode_config(&payload, base64::URL_SAFE_NO_PAD)
+            .map_err(|e| ApiError::new(&format!("Invalid JWT payload: {}", e)))?;
+        let claims: Value = serde_json::from_slice(&decoded)?;
+        if !claims.is_object() {
+            return Err(ApiError::new(
+                &"Invalid JWT: claims are not an object".to_string(),
+            ));
+        }
+        Ok(Self { claims: claims })
+    }
+
+    pub fn get_claim(&self, name: &str) -> Option<&Value> {
+        self.claims.get(name)
+    }
+
+    pub fn get_expiry(&self) -> Option<SystemTime> {
+        self.get_claim("exp")
+            .and_then(|e| e.as_f64())
+            .filter(|e| *e >= 0.)
+            .map(|e| UNIX_EPOCH + Duration::from_secs(e as u64))
+    }
+
+    // Expired tokens, or ones expiring within the margin
+    pub fn expires_within(&self, margin: Duration) -> bool {
+        match self.get_expiry() {
+            None => false,
+            Some(expiry) => SystemTime::now() + margin >= expiry,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn token(claims: &str) -> String {
+        format!(
+            "eyJhbGciOiJIUzI1NiJ9.{}.signature",
+            base64::encode_config(claims, base64::URL_SAFE_NO_PAD)
+        )
+    }
+
+    #[test]
+    fn test_parse() {
+        // "?" and ">" encode to URL safe characters
+        let jwt = Jwt::parse(&token(r#"{"exp": 4102444800, "sub": "??>>"}"#)).unwrap();
+        assert_eq!(
+            jwt.get_expiry(),
+            Some(UNIX_EPOCH + Duration::from_secs(4102444800))
+        );
+        assert_eq!(jwt.get_claim("sub").unwrap(), "??>>");
+        assert!(!jwt.expires_within(Duration::from_secs(3600)));
+
+        let expired = Jwt::parse(&token(r#"{"exp": 1000}"#)).unwrap();
+        assert!(expired.expires_within(Duration::from_secs(0)));
+
+        assert!(Jwt::parse(&"abc".to_string()).is_err());
+        assert!(Jwt::parse(&"a.!!!.c".to_string()).is_err());
+        assert!(Jwt::parse(&token("[1]")).is_err());
+
+        // Standard alphabet with padding
+        let standard = format!("e30.{}.s", base64::encode(r#"{"sub":"???"}"#));
+        assert!(standard.contains("/Pz8ifQ=="));
+        assert_eq!(
+            Jwt::parse(&standard).unwrap().get_claim("sub").unwrap(),
+            "???"
+        );
+    }
+}
@@ -1,122 +1,625 @@
 use super::account::KrakenAccount;
-use super::asset::GenericAsset;
+use super::asset::{Asset, GenericAsset};
+use super::cache::{default_cache_settings, CacheSettings, CacheStats, TtlCache};
+use super::config::{expand_home, Account as AccountConfig, Configuration};
+use super::cryptoprice::get_token_price;
 use super::domainconfig::DomainConfig;
 use super::error::ApiError;
+use super::krakenapi::PrivateClient;
+use super::krakenbalance::{
+    fetch_balances, fetch_margin_positions, fetch_open_orders, get_orders_holding,
+};
+use super::krakenledger::{get_positions, import, History, LedgerStore};
+use super::krakenrates::{fetch_rate_history, get_day, QuoteRates, RateHistory};
+use super::krakenstaking::{fetch_estimates, fetch_rewards, StakingYields};
+use super::provider::{boxed, require, AccountProvider};
 use kraken_client::Client;
-use regex::Regex;
+use serde::Deserialize;
 use std::collections::hash_map::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
-fn trading_symbol_lookup(symbol: &str) -> Result<String, ApiError> {
-    let pattern = Regex::new(r"(?P<trading_symbol>[A-Z0-9]{2,5})(\.(S|P))?").unwrap();
-    let capture = pattern.captures(&symbol);
-    if capture.is_none() {
-        return Err(ApiError::new(&format!(
-            "No valid Kraken Symbol: {}",
-            symbol
-        )));
+// New trades are imported hourly, failed imports are retried sooner
+const IMPORT_INTERVAL: Duration = Duration::from_secs(3600);
+const IMPORT_RETRY: Duration = Duration::from_secs(300);
+
+pub struct KrakenCache {
+    // Assets per API key
+    assets: TtlCache<String, Vec<GenericAsset>>,
+    // Public asset and pair list, shared by all accounts
+    metadata: TtlCache<String, KrakenMetadata>,
+    // Staking yields per API key, the rewards change at most daily
+    yields: TtlCache<String, StakingYields>,
+    // Daily USD rates of trade quote currencies per altname and first day
+    rates: TtlCache<String, RateHistory>,
+    // Imported trades and ledger entries per account name
+    ledger_store: Arc<LedgerStore>,
+    // Time of the next import per account name, None while one is running
+    imports: Arc<Mutex<HashMap<String, Option<Instant>>>>,
+}
+
+impl KrakenCache {
+    pub fn new(asset_settings: CacheSettings, ledger_file: Option<PathBuf>) -> Self {
+        Self {
+            assets: TtlCache::new(asset_settings),
+            metadata: TtlCache::new(CacheSettings::new(
+                Duration::from_secs(24 * 3600),
+                Duration::from_secs(7 * 24 * 3600),
+            )),
+            yields: TtlCache::new(CacheSettings::new(
+                Duration::from_secs(6 * 3600),
+                Duration::from_secs(7 * 24 * 3600),
+            )),
+            rates: TtlCache::new(CacheSettings::new(
+                Duration::from_secs(24 * 3600),
+                Duration::from_secs(7 * 24 * 3600),
+            )),
+            ledger_store: Arc::new(LedgerStore::new(ledger_file)),
+            imports: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    // The first import of an account can take many minutes with Kraken's rate
+    // limit, so it runs on its own and the assets get their profit and loss later
+    fn start_import(&self, client: &PrivateClient, account: &String) {
+        let mut imports = self.imports.lock().unwrap();
+        match imports.get(account) {
+            Some(None) => return,
+            Some(Some(next)) if Instant::now() < *next => return,
+            _ => imports.insert(account.clone(), None),
+        };
+        let client = client.clone();
+        let store = self.ledger_store.clone();
+        let imports = self.imports.clone();
+        let account = account.clone();
+        tokio::spawn(async move {
+            let next = match import(&client, &store, &account).await {
+                Ok(_) => IMPORT_INTERVAL,
+                Err(e) => {
+                    println!("Failed to import Kraken trades: {}", e.get_message());
+                    IMPORT_RETRY
+                }
+            };
+            imports
+                .lock()
+                .unwrap()
+                .insert(account, Some(Instant::now() + next));
+        });
+    }
+
+    pub fn get_stats(&self) -> Vec<(String, CacheStats)> {
+        vec![
+            ("kraken-assets".to_string(), self.assets.get_stats()),
+            ("kraken-metadata".to_string(), self.metadata.get_stats()),
+            ("kraken-yields".to_string(), self.yields.get_stats()),
+            ("kraken-rates".to_string(), self.rates.get_stats()),
+        ]
     }
-    Ok(capture.unwrap()["trading_symbol"].to_string())
 }
 
-async fn kraken_price_hashmap(
+static KRAKEN_CACHE: OnceLock<KrakenCache> = OnceLock::new();
+
+fn kraken_cache() -> &'static KrakenCache {
+    KRAKEN_CACHE.get_or_init(|| KrakenCache::new(default_cache_settings(), None))
+}
+
+// Staking and earn balances, e.g. DOT.S (staked), USDC.M (opt-in rewards),
+// ETH.F (auto earn) or ETH.B (bonded), are worth as much as the asset itself
+const BALANCE_SUFFIXES: [&str; 5] = [".S", ".M", ".F", ".B", ".P"];
+// Currencies tried for cross rates, e.g. DOT/EUR * EUR/USD
+const CROSS_CURRENCIES: [&str; 5] = ["EUR", "XBT", "ETH", "USDT", "USDC"];
+
+#[derive(Deserialize)]
+struct AssetInfo {
+    altname: String,
+}
+
+#[derive(Deserialize)]
+struct PairInfo {
+    altname: String,
+    base: String,
+    quote: String,
+}
+
+#[derive(Clone)]
+struct KrakenMetadata {
+    // Altname per asset code and altname, e.g. XXBT -> XBT, XBT -> XBT
+    altnames: HashMap<String, String>,
+    // Pair name per (base, quote) altnames, e.g. (XBT, USD) -> XXBTZUSD
+    pairs: HashMap<(String, String), String>,
+    // Base and quote altnames per pair name and altname,
+    // e.g. XXBTZUSD -> (XBT, USD) and XBTUSD -> (XBT, USD)
+    pair_assets: HashMap<String, (String, String)>,
+}
+
+impl KrakenMetadata {
+    fn new(assets: HashMap<String, AssetInfo>, pairs: HashMap<String, PairInfo>) -> Self {
+        let mut altnames = HashMap::new();
+        for (code, asset) in assets.into_iter() {
+            altnames.insert(asset.altname.clone(), asset.altname.clone());
+            altnames.insert(code, asset.altname);
+        }
+        let mut pair_assets = HashMap::new();
+        let mut by_assets = HashMap::new();
+        // Darkpool pairs (.d) have no tickers
+        for (name, pair) in pairs.into_iter().filter(|(name, _)| !name.ends_with(".d")) {
+            if let (Some(base), Some(quote)) = (altnames.get(&pair.base), altnames.get(&pair.quote))
+            {
+                let assets = (base.clone(), quote.clone());
+                pair_assets.insert(pair.altname, assets.clone());
+                pair_assets.insert(name.clone(), assets.clone());
+                by_assets.insert(assets, name);
+            }
+        }
+        Self {
+            altnames: altnames,
+            pairs: by_assets,
+            pair_assets: pair_assets,
+        }
+    }
+
+    // The altname of a balance key, e.g. XXBT -> XBT, DOT.S -> DOT.
+    // Balances with a suffix only keep it if it has pairs of its own, like ETH2.S.
+    fn normalize(&self, key: &str) -> String {
+        let altname = self.altnames.get(key).cloned().unwrap_or(key.to_string());
+        if self.has_pairs(&altname) {
+            return altname;
+        }
+        for suffix in BALANCE_SUFFIXES.iter() {
+            if let Some(base) = altname.strip_suffix(suffix) {
+                return self.normalize(base);
+            }
+        }
+        altname
+    }
+
+    fn has_pairs(&self, altname: &String) -> bool {
+        self.pairs
+            .keys()
+            .any(|(base, quote)| base == altname || quote == altname)
+    }
+
+    // Pair name and whether its price has to be inverted
+    fn find_pair(&self, from: &String, to: &String) -> Option<(String, bool)> {
+        if let Some(pair) = self.pairs.get(&(from.clone(), to.clone())) {
+            return Some((pair.clone(), false));
+        }
+        self.pairs
+            .get(&(to.clone(), from.clone()))
+            .map(|pair| (pair.clone(), true))
+    }
+
+    // Base and quote altnames of a pair, e.g. XXBTZEUR -> (XBT, EUR)
+    fn pair_assets(&self, name: &str) -> Option<(String, String)> {
+        self.pair_assets.get(name).cloned()
+    }
+
+    // Pairs to multiply for the USD price, directly or via a cross currency
+    fn find_route(&self, altname: &String) -> Option<Vec<(String, bool)>> {
+        let usd = "USD".to_string();
+        if altname == &usd {
+            return Some(Vec::new());
+        }
+        if let Some(pair) = self.find_pair(altname, &usd) {
+            return Some(vec![pair]);
+        }
+        for cross in CROSS_CURRENCIES.iter().map(|c| c.to_string()) {
+            if let (Some(first), Some(second)) = (
+                self.find_pair(altname, &cross),
+                self.find_pair(&cross, &usd),
+            ) {
+                return Some(vec![first, second]);
+            }
+        }
+        None
+    }
+}
+
+async fn fetch_metadata(client: &Client) -> Result<KrakenMetadata, ApiError> {
+    let assets = client
+        .get_assets()
+        .execute::<HashMap<String, AssetInfo>>()
+        .await?;
+    let pairs = client
+        .get_asset_pairs()
+        .execute::<HashMap<String, PairInfo>>()
+        .await?;
+    Ok(KrakenMetadata::new(assets, pairs))
+}
+
+// Readable symbol for the assets, e.g. XBT -> BTC
+fn display_symbol(altname: &String) -> String {
+    match altname.as_str() {
+        "XBT" => "BTC".to_string(),
+        "XDG" => "DOGE".to_string(),
+        a => a.to_string(),
+    }
+}
+
+// USD price per altname. Assets without a Kraken pair are looked up
+// with the token price sources, ones without any price are left out.
+async fn get_usd_prices(
     client: &Client,
-    symbols: &Vec<String>,
-) -> Result<HashMap<String, f32>, ApiError> {
-    let tickers = symbols
-        .iter()
-        .filter(|x| *x != "ZUSD")
-        .filter(|x| *x != "ETH2")
-        .filter(|x| *x != "ZEUR")
-        .map(|x| {
-            if x.starts_with("X") && (x != "XTZ") {
-                format!("{}ZUSD", x)
-            } else {
-                format!("{}USD", x)
+    metadata: &KrakenMetadata,
+    altnames: &Vec<String>,
+) -> HashMap<String, f32> {
+    let mut routes = HashMap::new();
+    let mut pair_names: Vec<String> = Vec::new();
+    for altname in altnames.iter() {
+        if let Some(route) = metadata.find_route(altname) {
+            for (pair, _) in route.iter() {
+                if !pair_names.contains(pair) {
+                    pair_names.push(pair.clone());
+                }
             }
-        })
-        .collect::<Vec<String>>()
-        .join(",");
-    let tickers = tickers + ",ETH2.SETH,XETHZUSD,ZEURZUSD";
-    let response = client.get_tickers(&tickers).send().await?;
-    let mut result = HashMap::new();
-    result.insert("ZUSD".to_string(), 1.0);
-    let x_pattern = Regex::new(r"(?P<trading_symbol>[A-Z]{2,4})ZUSD").unwrap();
-    let pattern = Regex::new(r"(?P<trading_symbol>[A-Z]{2,4})USD").unwrap();
-
-    let mut eth2eth = None;
-    for (trading_pair_symbol, ticker) in response.iter() {
-        let price = ticker.c[0].parse::<f32>().unwrap();
-        if trading_pair_symbol == "ETH2.SETH" {
-            eth2eth = Some(price);
-            continue;
-        }
-
-        let x_capture = x_pattern.captures(&trading_pair_symbol);
-        let capture = pattern.captures(&trading_pair_symbol);
-        if capture.is_none() && x_capture.is_none() {
-            return Err(ApiError::new(&format!(
-                "No valid Kraken Pair Symbol: {}",
-                trading_pair_symbol
-            )));
-        }
-        let key = if x_capture.is_some() {
-            x_capture.unwrap()["trading_symbol"].to_string()
-        } else {
-            capture.unwrap()["trading_symbol"].to_string()
-        };
-        result.insert(key, price);
+            routes.insert(altname.clone(), route);
+        }
     }
-    if eth2eth.is_none() {
-        return Err(ApiError::new(&"Could not look up ETH2 price!".to_string()));
+
+    let mut tickers = HashMap::new();
+    if !pair_names.is_empty() {
+        match client.get_tickers(&pair_names.join(",")).send().await {
+            Ok(response) => {
+                for (pair, ticker) in response {
+                    let price = ticker.c.get(0).and_then(|c| c.parse::<f32>().ok());
+                    if let Some(price) = price {
+                        tickers.insert(pair, price);
+                    }
+                }
+            }
+            Err(e) => println!(
+                "Failed to get Kraken tickers: {}",
+                ApiError::from(e).get_message()
+            ),
+        }
     }
-    result.insert(
-        "ETH2".to_string(),
-        eth2eth.unwrap() * result.get("XETH").unwrap(),
-    );
-    Ok(result)
+
+    let mut prices = HashMap::new();
+    for altname in altnames.iter() {
+        let routed = routes.get(altname).and_then(|route| {
+            route
+                .iter()
+                .try_fold(1.0, |price, (pair, invert)| match tickers.get(pair) {
+                    Some(p) if *invert && *p > 0. => Some(price / p),
+                    Some(p) if !*invert => Some(price * p),
+                    _ => None,
+                })
+        });
+        let price = match routed {
+            Some(p) => Ok(p),
+            None => get_token_price(&display_symbol(altname)).await,
+        };
+        match price {
+            Ok(p) => {
+                prices.insert(altname.clone(), p);
+            }
+            Err(e) => println!("No price for Kraken asset {}: {}", altname, e.get_message()),
+        }
+    }
+    prices
 }
 
-async fn get_apy(_client: &Client, symbol: &str) -> Result<f32, ApiError> {
-    let apr = match symbol {
-        "DOT.S" => 0.12,
-        "ETH2.S" => 0.03,
-        _ => 0.0,
-    };
-    Ok(apr)
+// Suffix of staked balances, e.g. .S of DOT.S
+fn staking_suffix(key: &str) -> Option<&str> {
+    BALANCE_SUFFIXES
+        .iter()
+        .find(|suffix| key.ends_with(*suffix))
+        .copied()
+}
+
+async fn fetch_yields(
+    client: &PrivateClient,
+    metadata: &KrakenMetadata,
+) -> Result<StakingYields, ApiError> {
+    let rewards = fetch_rewards(client).await?;
+    // Without the strategies, assets without rewards have no growth
+    let estimates = fetch_estimates(client).await.unwrap_or_else(|e| {
+        println!("Failed to get Kraken Earn strategies: {}", e.get_message());
+        Vec::new()
+    });
+    Ok(StakingYields::new(&rewards, estimates, |asset| {
+        metadata.normalize(asset)
+    }))
+}
+
+// Rates of the quote currencies of the trades since their first trade.
+// Trades in a currency without rates have no known cost.
+async fn fetch_quote_rates(
+    cache: &KrakenCache,
+    client: &Client,
+    metadata: &KrakenMetadata,
+    history: &History,
+    pair_assets: &HashMap<String, (String, String)>,
+) -> QuoteRates {
+    let mut since: HashMap<String, i64> = HashMap::new();
+    for trade in history.trades.values() {
+        if let Some((_, quote)) = pair_assets.get(&trade.pair) {
+            let day = since.entry(quote.clone()).or_insert(i64::MAX);
+            *day = (*day).min(get_day(trade.time));
+        }
+    }
+    let mut rates = QuoteRates::default();
+    for (quote, since) in since.into_iter().filter(|(quote, _)| quote != "USD") {
+        let history = cache
+            .rates
+            .get_or_fetch(format!("{} {}", quote, since), || {
+                fetch_rate_history(client, &quote, since, metadata.find_route(&quote))
+            })
+            .await;
+        match history {
+            Ok(history) => rates.insert(quote, history),
+            Err(e) => println!(
+                "No rates of {} for Kraken trades: {}",
+                quote,
+                e.get_message()
+            ),
+        }
+    }
+    rates
 }
 
 pub async fn get_assets_of_kraken_account(
-    _domainconfig: &DomainConfig,
+    cache: &KrakenCache,
     account: &KrakenAccount,
 ) -> Result<Vec<GenericAsset>, ApiError> {
-    let client = Client::new(account.api_key.as_str(), account.api_secret.as_str());
+    cache
+        .assets
+        .get_or_fetch(account.api_key.clone(), || fetch_assets(cache, account))
+        .await
+}
 
-    let response = client.get_account_balance().send().await?;
+async fn fetch_assets(
+    cache: &KrakenCache,
+    account: &KrakenAccount,
+) -> Result<Vec<GenericAsset>, ApiError> {
+    let client = PrivateClient::new(&account.api_key, &account.api_secret);
+    // Public calls need no nonce
+    let public_client = Client::default();
 
-    let response = response
-        .iter()
-        .map(|(key, value)| (key.to_string(), value.parse::<f32>().unwrap()))
-        .filter(|(_, value)| *value > 0.000000001)
-        .collect::<Vec<(String, f32)>>();
+    let balances = fetch_balances(&client).await?;
 
-    let symbols = response
+    let metadata = cache
+        .metadata
+        .get_or_fetch("metadata".to_string(), || fetch_metadata(&public_client))
+        .await?;
+    // Until the first import finished the assets have no profit and loss
+    cache.start_import(&client, &account.name);
+    let history = cache.ledger_store.get_complete(&account.name);
+    let mut pair_assets = HashMap::new();
+    for trade in history.iter().flat_map(|h| h.trades.values()) {
+        if let Some(assets) = metadata.pair_assets(&trade.pair) {
+            pair_assets.insert(trade.pair.clone(), assets);
+        }
+    }
+
+    let margin_positions = fetch_margin_positions(&client).await.unwrap_or_else(|e| {
+        println!("Failed to get Kraken margin positions: {}", e.get_message());
+        Vec::new()
+    });
+    for position in margin_positions.iter() {
+        if let Some(assets) = metadata.pair_assets(&position.pair) {
+            pair_assets.insert(position.pair.clone(), assets);
+        }
+    }
+
+    let mut altnames: Vec<String> = balances
         .iter()
-        .map(|(key, _)| trading_symbol_lookup(key).ok().unwrap())
-        .collect::<Vec<String>>();
+        .map(|b| metadata.normalize(&b.key))
+        // Margin positions are valued in their quote currency
+        .chain(
+            margin_positions
+                .iter()
+                .filter_map(|p| pair_assets.get(&p.pair))
+                .map(|(_, quote)| quote.clone()),
+        )
+        .collect();
+    altnames.sort();
+    altnames.dedup();
+    let prices = get_usd_prices(&public_client, &metadata, &altnames).await;
 
-    let kraken_hashmap = kraken_price_hashmap(&client, &symbols).await?;
+    let staking = balances.iter().any(|b| staking_suffix(&b.key).is_some());
+    let mut yields = StakingYields::default();
+    if staking {
+        yields = cache
+            .yields
+            .get_or_fetch(account.api_key.clone(), || fetch_yields(&client, &metadata))
+            .await
+            .unwrap_or_else(|e| {
+                println!("Failed to get Kraken staking rewards: {}", e.get_message());
+                StakingYields::default()
+            });
+    }
+
+    let positions = match &history {
+        Some(history) => {
+            let rates =
+                fetch_quote_rates(cache, &public_client, &metadata, history, &pair_assets).await;
+            get_positions(
+                history,
+                |pair| pair_assets.get(pair).cloned(),
+                |asset| metadata.normalize(asset),
+                |quote, time| rates.get(quote, time),
+            )
+        }
+        None => HashMap::new(),
+    };
+    let mut total_units: HashMap<String, f32> = HashMap::new();
+    for balance in balances.iter() {
+        *total_units
+            .entry(metadata.normalize(&balance.key))
+            .or_default() += balance.get_total();
+    }
+    let mut orders = Vec::new();
+    if balances.iter().any(|b| b.locked > 0.) {
+        orders = fetch_open_orders(&client).await.unwrap_or_else(|e| {
+            println!("Failed to get Kraken open orders: {}", e.get_message());
+            Vec::new()
+        });
+    }
 
     let mut result = Vec::new();
-    for (key, value) in response {
-        let trading_symbol = trading_symbol_lookup(&key.to_string())?;
-        result.push(GenericAsset::new(
-            get_apy(&client, &key).await?,
-            trading_symbol.clone(),
-            format!("{} on Kraken", key),
-            value,
-            *kraken_hashmap.get(&trading_symbol).unwrap_or(&0.0),
-        ))
+    for balance in balances {
+        let altname = metadata.normalize(&balance.key);
+        let price = prices.get(&altname).copied();
+        let apy = match staking_suffix(&balance.key) {
+            Some(suffix) => yields.get_yield(&balance.key, &altname, suffix),
+            None => 0.0,
+        };
+        let asset = |units: f32, description: String| {
+            let asset = GenericAsset::new(
+                apy,
+                display_symbol(&altname),
+                description,
+                units,
+                price.unwrap_or(0.0),
+            );
+            match (price, &history) {
+                (Some(price), Some(_)) => {
+                    let position = positions.get(&altname).cloned().unwrap_or_default();
+                    match position.get_pnl(units, total_units[&altname], price) {
+                        Some(pnl) => asset.with_pnl(pnl),
+                        // Left out rather than guessed
+                        None => asset,
+                    }
+                }
+                (Some(_), None) => asset,
+                // Still listed, so that unknown assets are noticed
+                (None, _) => asset.as_unpriced(),
+            }
+        };
+        if balance.available > 0. {
+            result.push(asset(
+                balance.available,
+                format!("{} on Kraken", balance.key),
+            ));
+        }
+        if balance.locked > 0. {
+            let holding = get_orders_holding(&orders, &altname, |pair| metadata.pair_assets(pair));
+            let mut description = format!("{} locked on Kraken", balance.key);
+            if !holding.is_empty() {
+                description = format!("{} by {}", description, holding.join(", "));
+            }
+            result.push(asset(balance.locked, description));
+        }
+    }
+
+    for position in margin_positions {
+        let assets = pair_assets.get(&position.pair);
+        let rate = assets.and_then(|(_, quote)| prices.get(quote));
+        let asset = match (assets, rate) {
+            (Some((base, _)), Some(rate)) => position.to_asset(display_symbol(base), *rate)?,
+            _ => position.to_asset(position.pair.clone(), 0.0)?.as_unpriced(),
+        };
+        result.push(asset);
     }
 
     Ok(result)
 }
+
+#[rocket::async_trait]
+impl AccountProvider for KrakenAccount {
+    fn from_config(name: &String, config: &AccountConfig) -> Result<Self, String> {
+        Ok(Self {
+            name: name.clone(),
+            api_key: require(&config.api_key, "api-key", name, "kraken")?,
+            api_secret: require(&config.api_secret, "api-secret", name, "kraken")?,
+        })
+    }
+
+    fn configure(config: &Configuration) {
+        let _ = KRAKEN_CACHE.set(KrakenCache::new(
+            config.get_cache_settings("kraken", default_cache_settings()),
+            // Imported trades, Kraken only returns 50 per call
+            Some(PathBuf::from(expand_home(
+                config
+                    .kraken_ledger_file
+                    .as_ref()
+                    .unwrap_or(&"~/.get-rich-slow-kraken-ledger.json".to_string()),
+            ))),
+        ));
+    }
+
+    fn get_cache_stats() -> Vec<(String, CacheStats)> {
+        kraken_cache().get_stats()
+    }
+
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_kind(&self) -> String {
+        "kraken".to_string()
+    }
+
+    async fn get_assets(
+        &self,
+        _domainconfig: &DomainConfig,
+    ) -> Result<Vec<Box<dyn Asset>>, ApiError> {
+        Ok(boxed(
+            get_assets_of_kraken_account(kraken_cache(), self).await?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn metadata() -> KrakenMetadata {
+        let assets: HashMap<String, AssetInfo> = serde_json::from_value(serde_json::json!({
+            "XXBT": {"altname": "XBT"},
+            "XETH": {"altname": "ETH"},
+            "ETH2": {"altname": "ETH2"},
+            "ETH2.S": {"altname": "ETH2.S"},
+            "DOT": {"altname": "DOT"},
+            "DOT.S": {"altname": "DOT.S"},
+            "KFEE": {"altname": "FEE"},
+            "ZEUR": {"altname": "EUR"},
+            "ZUSD": {"altname": "USD"}
+        }))
+        .unwrap();
+        let pairs: HashMap<String, PairInfo> = serde_json::from_value(serde_json::json!({
+            "XXBTZUSD": {"altname": "XBTUSD", "base": "XXBT", "quote": "ZUSD"},
+            "XXBTZUSD.d": {"altname": "XBTUSD.d", "base": "XXBT", "quote": "ZUSD"},
+            "XETHZUSD": {"altname": "ETHUSD", "base": "XETH", "quote": "ZUSD"},
+            "ETH2.SETH": {"altname": "ETH2.SETH", "base": "ETH2.S", "quote": "XETH"},
+            "DOTEUR": {"altname": "DOTEUR", "base": "DOT", "quote": "ZEUR"},
+            "ZEURZUSD": {"altname": "EURUSD", "base": "ZEUR", "quote": "ZUSD"}
+        }))
+        .unwrap();
+        KrakenMetadata::new(assets, pairs)
+    }
+
+    #[test]
+    fn test_normalize() {
+        let metadata = metadata();
+        assert_eq!(metadata.normalize("XXBT"), "XBT");
+        assert_eq!(metadata.normalize("XBT.M"), "XBT");
+        assert_eq!(metadata.normalize("DOT.S"), "DOT");
+        assert_eq!(metadata.normalize("ETH2.S"), "ETH2.S");
+        assert_eq!(metadata.normalize("ETH.F"), "ETH");
+        assert_eq!(metadata.normalize("ZUSD"), "USD");
+        assert_eq!(metadata.normalize("NEW"), "NEW");
+    }
+
+    #[test]
+    fn test_find_route() {
+        let metadata = metadata();
+        let pair = |name: &str, invert: bool| (name.to_string(), invert);
+        assert_eq!(
+            metadata.find_route(&"XBT".to_string()),
+            Some(vec![pair("XXBTZUSD", false)])
+        );
+        assert_eq!(
+            metadata.find_route(&"DOT".to_string()),
+            Some(vec![pair("DOTEUR", false), pair("ZEURZUSD", false)])
+        );
+        assert_eq!(
+            metadata.find_route(&"ETH2.S".to_string()),
+            Some(vec![pair("ETH2.SETH", false), pair("XETHZUSD", false)])
+        );
+        assert_eq!(metadata.find_route(&"USD".to_string()), Some(Vec::new()));
+        assert_eq!(metadata.find_route(&"FEE".to_string()), None);
+    }
+}
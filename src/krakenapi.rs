@@ -0,0 +1,209 @@
+use super::error::ApiError;
+use kraken_client::error::Error;
+use kraken_client::Client;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+// Private calls of the Kraken API. Kraken rejects a nonce lower than the last
+// one of the API key, so the calls of a key are sent one after the other, and
+// retried if Kraken still reports an invalid nonce or its rate limit.
+
+const MAX_RETRIES: u32 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KrakenError {
+    // EAPI:Invalid nonce
+    InvalidNonce,
+    // E.g. EAPI:Rate limit exceeded or EGeneral:Temporary lockout
+    RateLimited(String),
+    // E.g. EAPI:Invalid key or EGeneral:Permission denied
+    Unauthorized(String),
+    // E.g. EService:Unavailable or a failed HTTP request
+    Unavailable(String),
+    Api(String),
+    // Unexpected values in a response, e.g. a balance that is not a number
+    Parse(String),
+}
+
+impl KrakenError {
+    // Kraken reports errors as "<severity><category>:<message>", several joined by ","
+    fn from_api(errors: &String) -> Self {
+        let errors_lowercase = errors.to_lowercase();
+        let contains = |s: &str| errors_lowercase.contains(s);
+        if contains("invalid nonce") {
+            KrakenError::InvalidNonce
+        } else if contains("rate limit") || contains("temporary lockout") {
+            KrakenError::RateLimited(errors.clone())
+        } else if contains("invalid key")
+            || contains("invalid signature")
+            || contains("permission denied")
+        {
+            KrakenError::Unauthorized(errors.clone())
+        } else if contains("eservice:") {
+            KrakenError::Unavailable(errors.clone())
+        } else {
+            KrakenError::Api(errors.clone())
+        }
+    }
+
+    fn retry_delay(&self, attempt: u32) -> Option<Duration> {
+        match self {
+            KrakenError::InvalidNonce => Some(Duration::from_secs(1)),
+            // The rate limit counter decreases by at least one every three seconds
+            KrakenError::RateLimited(_) => Some(Duration::from_secs(5 << attempt)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for KrakenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KrakenError::InvalidNonce => write!(f, "invalid nonce"),
+            KrakenError::RateLimited(e) => write!(f, "rate limited: {}", e),
+            KrakenError::Unauthorized(e) => write!(f, "not authorized: {}", e),
+            KrakenError::Unavailable(e) => write!(f, "unavailable: {}", e),
+            KrakenError::Api(e) => write!(f, "{}", e),
+            KrakenError::Parse(e) => write!(f, "unexpected response: {}", e),
+        }
+    }
+}
+
+impl From<Error> for KrakenError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Api(errors) => KrakenError::from_api(&errors),
+            Error::Unauthorized => KrakenError::Unauthorized("missing API key".to_string()),
+            Error::FailedRequest { err, status } => match status {
+                Some(429) => KrakenError::RateLimited(err),
+                _ => KrakenError::Unavailable(err),
+            },
+            Error::Internal(e) => KrakenError::Parse(e),
+        }
+    }
+}
+
+impl From<KrakenError> for ApiError {
+    fn from(err: KrakenError) -> Self {
+        let message = format!("Kraken API Error: {}", err);
+        match err {
+            KrakenError::Unavailable(_) | KrakenError::Unauthorized(_) => {
+                ApiError::unavailable(&message)
+            }
+            _ => ApiError::new(&message),
+        }
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(err: Error) -> Self {
+        Self::from(KrakenError::from(err))
+    }
+}
+
+// What is parsed is named in the error, e.g. "balance of XXBT"
+pub fn parse_number(value: &str, what: &str) -> Result<f32, KrakenError> {
+    value
+        .trim()
+        .parse::<f32>()
+        .map_err(|_| KrakenError::Parse(format!("{} is not a number: {:?}", what, value)))
+}
+
+// One lock per API key
+fn get_key_lock(api_key: &String) -> Arc<tokio::sync::Mutex<()>> {
+    static LOCKS: OnceLock<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> = OnceLock::new();
+    let mut locks = LOCKS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap();
+    locks.entry(api_key.clone()).or_default().clone()
+}
+
+#[derive(Clone)]
+pub struct PrivateClient {
+    api_key: String,
+    client: Client,
+}
+
+impl PrivateClient {
+    pub fn new(api_key: &String, api_secret: &String) -> Self {
+        Self {
+            api_key: api_key.clone(),
+            client: Client::new(api_key.as_str(), api_secret.as_str()),
+        }
+    }
+
+    pub async fn send<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: Option<String>,
+    ) -> Result<T, KrakenError> {
+        let lock = get_key_lock(&self.api_key);
+        let _guard = lock.lock().await;
+        let mut attempt = 0;
+        loop {
+            let error = match self.client.send_private(path, query.clone()).await {
+                Ok(result) => return Ok(result),
+                Err(e) => KrakenError::from(e),
+            };
+            match error.retry_delay(attempt) {
+                Some(delay) if attempt < MAX_RETRIES => {
+                    println!(
+                        "Kraken {} failed ({}), retrying in {} seconds",
+                        path,
+                        error,
+                        delay.as_secs()
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                _ => return Err(error),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_errors() {
+        let error = |e: &str| KrakenError::from(Error::Api(e.to_string()));
+        assert_eq!(error("EAPI:Invalid nonce"), KrakenError::InvalidNonce);
+        assert!(matches!(
+            error("EAPI:Rate limit exceeded"),
+            KrakenError::RateLimited(_)
+        ));
+        assert!(matches!(
+            error("EGeneral:Permission denied"),
+            KrakenError::Unauthorized(_)
+        ));
+        assert!(matches!(
+            error("EService:Unavailable"),
+            KrakenError::Unavailable(_)
+        ));
+        assert!(matches!(
+            error("EQuery:Unknown asset pair"),
+            KrakenError::Api(_)
+        ));
+        assert_eq!(
+            KrakenError::RateLimited(String::new()).retry_delay(2),
+            Some(Duration::from_secs(20))
+        );
+        assert_eq!(KrakenError::Api(String::new()).retry_delay(0), None);
+        assert!(ApiError::from(error("EGeneral:Permission denied")).is_unavailable());
+        assert!(!ApiError::from(error("EQuery:Unknown asset pair")).is_unavailable());
+
+        assert_eq!(parse_number(" 1.5", "balance of XXBT"), Ok(1.5));
+        assert_eq!(
+            parse_number("n/a", "balance of XXBT"),
+            Err(KrakenError::Parse(
+                "balance of XXBT is not a number: \"n/a\"".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use super::asset::{GenericAsset, ProfitAndLoss};
+use super::error::ApiError;
+use super::krakenapi::{parse_number, PrivateClient};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// Kraken balances include funds held for open orders and as margin of open
+// positions. The extended balance tells how much of each is held, open orders
+// and margin positions tell what for.
+
+#[derive(Deserialize)]
+struct ExtendedBalance {
+    balance: String,
+    // Held for open orders and margin positions
+    hold_trade: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Balance {
+    pub key: String,
+    pub available: f32,
+    pub locked: f32,
+}
+
+impl Balance {
+    pub fn get_total(&self) -> f32 {
+        self.available + self.locked
+    }
+}
+
+fn parse_balances(balances: HashMap<String, ExtendedBalance>) -> Result<Vec<Balance>, ApiError> {
+    let mut result = Vec::new();
+    for (key, balance) in balances.into_iter() {
+        let total = parse_number(&balance.balance, &format!("balance of {}", key))?;
+        if total <= 0.000000001 {
+            continue;
+        }
+        let locked = match balance.hold_trade {
+            Some(hold) => parse_number(&hold, &format!("held balance of {}", key))?
+                .max(0.)
+                .min(total),
+            None => 0.,
+        };
+        result.push(Balance {
+            key: key,
+            available: total - locked,
+            locked: locked,
+        });
+    }
+    result.sort_by(|a, b| a.key.cmp(&b.key));
+    Ok(result)
+}
+
+pub async fn fetch_balances(client: &PrivateClient) -> Result<Vec<Balance>, ApiError> {
+    let balances: HashMap<String, ExtendedBalance> =
+        client.send("/0/private/BalanceEx", None).await?;
+    parse_balances(balances)
+}
+
+#[derive(Deserialize)]
+struct OpenOrders {
+    open: HashMap<String, OpenOrder>,
+}
+
+#[derive(Deserialize)]
+struct OpenOrder {
+    descr: Order,
+}
+
+#[derive(Deserialize)]
+pub struct Order {
+    // Pair altname, e.g. XBTUSD
+    pub pair: String,
+    // buy or sell
+    #[serde(rename = "type")]
+    pub kind: String,
+    // E.g. "sell 0.50000000 XBTUSD @ limit 45000.0"
+    pub order: String,
+}
+
+pub async fn fetch_open_orders(client: &PrivateClient) -> Result<Vec<Order>, ApiError> {
+    let orders: OpenOrders = client.send("/0/private/OpenOrders", None).await?;
+    Ok(orders.open.into_values().map(|o| o.descr).collect())
+}
+
+// Buy orders hold the quote currency, sell orders the base currency
+pub fn get_orders_holding(
+    orders: &Vec<Order>,
+    altname: &String,
+    pair_assets: impl Fn(&str) -> Option<(String, String)>,
+) -> Vec<String> {
+    let mut result: Vec<String> = orders
+        .iter()
+        .filter(
+            |order| match (pair_assets(&order.pair), order.kind.as_str()) {
+                (Some((_, quote)), "buy") => &quote == altname,
+                (Some((base, _)), "sell") => &base == altname,
+                _ => false,
+            },
+        )
+        .map(|order| order.order.clone())
+        .collect();
+    result.sort();
+    result
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct MarginPosition {
+    // Pair name, e.g. XXBTZUSD
+    pub pair: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    // Opening cost in the quote currency
+    pub cost: String,
+    pub vol: String,
+    pub vol_closed: String,
+    // Current value and unrealized profit in the quote currency
+    pub value: Option<String>,
+    pub net: Option<String>,
+}
+
+impl MarginPosition {
+    // The margin is part of the held balance already, the position adds its
+    // unrealized profit to the portfolio value. usd_rate is of the quote currency.
+    pub fn to_asset(&self, symbol: String, usd_rate: f32) -> Result<GenericAsset, ApiError> {
+        let what = |field: &str| format!("{} of margin position {}", field, self.pair);
+        let vol = parse_number(&self.vol, &what("vol"))?;
+        let units = vol - parse_number(&self.vol_closed, &what("vol_closed"))?;
+        let number = |value: &Option<String>, field: &str| match value {
+            Some(v) => parse_number(v, &what(field)),
+            None => Ok(0.),
+        };
+        let net = number(&self.net, "net")? * usd_rate;
+        let value = number(&self.value, "value")? * usd_rate;
+        let mut cost = 0.;
+        if vol > 0. {
+            cost = parse_number(&self.cost, &what("cost"))? * units / vol * usd_rate;
+        }
+        let mut unit_price = 0.;
+        if units > 0. {
+            unit_price = net / units;
+        }
+        Ok(GenericAsset::new(
+            0.0,
+            symbol,
+            format!(
+                "{} margin position {} worth {:.2} USD on Kraken",
+                self.kind, self.pair, value
+            ),
+            units,
+            unit_price,
+        )
+        .with_pnl(ProfitAndLoss {
+            cost_basis: cost,
+            realized: 0.,
+            unrealized: net,
+        }))
+    }
+}
+
+// Needs the "Query Open Orders & Trades" permission of the API key
+pub async fn fetch_margin_positions(
+    client: &PrivateClient,
+) -> Result<Vec<MarginPosition>, ApiError> {
+    let positions: HashMap<String, MarginPosition> = client
+        .send("/0/private/OpenPositions", Some("docalc=true".to_string()))
+        .await?;
+    let mut positions: Vec<MarginPosition> = positions.into_values().collect();
+    positions.sort_by(|a, b| a.pair.cmp(&b.pair));
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asset::Asset;
+
+    #[test]
+    fn test_balances() {
+        let balances = serde_json::from_value(serde_json::json!({
+            "XXBT": {"balance": "1.5", "hold_trade": "0.5"},
+            "ZUSD": {"balance": "100.0", "hold_trade": "0.0000"},
+            "DOT.S": {"balance": "10"},
+            "ZEUR": {"balance": "0.0000", "hold_trade": "0.0000"}
+        }))
+        .unwrap();
+        let balances = parse_balances(balances).unwrap();
+        assert_eq!(balances.len(), 3);
+        assert_eq!(
+            balances[1],
+            Balance {
+                key: "XXBT".to_string(),
+                available: 1.0,
+                locked: 0.5
+            }
+        );
+        assert_eq!(balances[0].locked, 0.);
+
+        let orders: OpenOrders = serde_json::from_value(serde_json::json!({"open": {
+            "O1": {"descr": {"pair": "XBTUSD", "type": "sell", "order": "sell 0.5 XBTUSD @ limit 45000"}},
+            "O2": {"descr": {"pair": "XBTUSD", "type": "buy", "order": "buy 0.1 XBTUSD @ limit 20000"}}
+        }}))
+        .unwrap();
+        let orders: Vec<Order> = orders.open.into_values().map(|o| o.descr).collect();
+        let pair_assets = |_: &str| Some(("XBT".to_string(), "USD".to_string()));
+        assert_eq!(
+            get_orders_holding(&orders, &"XBT".to_string(), pair_assets),
+            vec!["sell 0.5 XBTUSD @ limit 45000"]
+        );
+        assert_eq!(
+            get_orders_holding(&orders, &"USD".to_string(), pair_assets),
+            vec!["buy 0.1 XBTUSD @ limit 20000"]
+        );
+    }
+
+    #[test]
+    fn test_margin_position() {
+        let position: MarginPosition = serde_json::from_value(serde_json::json!({
+            "pair": "XXBTZEUR", "type": "buy", "cost": "20000", "vol": "1", "vol_closed": "0.5",
+            "margin": "4000", "value": "12000", "net": "+2000"
+        }))
+        .unwrap();
+        let asset = position.to_asset("BTC".to_string(), 1.25).unwrap();
+        assert_eq!(asset.get_units(), 0.5);
+        assert_eq!(asset.get_units() * asset.get_unit_price(), 2500.);
+        assert_eq!(
+            asset.get_pnl(),
+            Some(ProfitAndLoss {
+                cost_basis: 12500.,
+                realized: 0.,
+                unrealized: 2500.
+            })
+        );
+        assert!(asset.get_description().contains("worth 15000.00 USD"));
+    }
+}
@@ -0,0 +1,610 @@
+use super::asset::ProfitAndLoss;
+use super::error::ApiError;
+use super::krakenapi::PrivateClient;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Trades and ledger entries of Kraken accounts. Kraken returns 50 entries
+// per call, so they are imported once and kept in a file, later imports
+// only ask for newer entries.
+
+const PAGE_SIZE: usize = 50;
+const MAX_PAGES: usize = 100;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LedgerEntry {
+    #[serde(rename = "type")]
+    pub kind: String,
+    // E.g. reward of an earn entry
+    #[serde(default)]
+    pub subtype: String,
+    pub asset: String,
+    pub time: f64,
+    pub amount: String,
+    #[serde(default)]
+    pub fee: String,
+    pub balance: String,
+}
+
+impl LedgerEntry {
+    // Staking rewards and Earn rewards. Transfers between spot and staking are
+    // of kind transfer, Earn allocations are earn entries of another subtype.
+    pub fn is_reward(&self) -> bool {
+        self.kind == "staking" || (self.kind == "earn" && self.subtype == "reward")
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Trade {
+    pub pair: String,
+    pub time: f64,
+    // buy or sell
+    #[serde(rename = "type")]
+    pub kind: String,
+    // In the quote currency of the pair
+    pub cost: String,
+    pub fee: String,
+    pub vol: String,
+}
+
+trait Page: DeserializeOwned {
+    type Entry: Clone;
+    // Entries of the page and the number of all entries
+    fn into_entries(self) -> (HashMap<String, Self::Entry>, usize);
+    fn get_time(entry: &Self::Entry) -> f64;
+    // Where the entries of this kind are kept
+    fn get_part(history: &mut History) -> (&mut HashMap<String, Self::Entry>, &mut ImportCursor);
+}
+
+#[derive(Deserialize)]
+struct LedgerPage {
+    ledger: HashMap<String, LedgerEntry>,
+    count: usize,
+}
+
+impl Page for LedgerPage {
+    type Entry = LedgerEntry;
+    fn into_entries(self) -> (HashMap<String, LedgerEntry>, usize) {
+        (self.ledger, self.count)
+    }
+    fn get_time(entry: &LedgerEntry) -> f64 {
+        entry.time
+    }
+    fn get_part(history: &mut History) -> (&mut HashMap<String, LedgerEntry>, &mut ImportCursor) {
+        (&mut history.ledger, &mut history.ledger_cursor)
+    }
+}
+
+#[derive(Deserialize)]
+struct TradesPage {
+    trades: HashMap<String, Trade>,
+    count: usize,
+}
+
+impl Page for TradesPage {
+    type Entry = Trade;
+    fn into_entries(self) -> (HashMap<String, Trade>, usize) {
+        (self.trades, self.count)
+    }
+    fn get_time(trade: &Trade) -> f64 {
+        trade.time
+    }
+    fn get_part(history: &mut History) -> (&mut HashMap<String, Trade>, &mut ImportCursor) {
+        (&mut history.trades, &mut history.trades_cursor)
+    }
+}
+
+fn build_query(query: &str, start: u64, end: Option<u64>, offset: usize) -> String {
+    let mut params = Vec::new();
+    if !query.is_empty() {
+        params.push(query.to_string());
+    }
+    params.push(format!("start={}", start));
+    if let Some(end) = end {
+        params.push(format!("end={}", end));
+    }
+    params.push(format!("ofs={}", offset));
+    params.join("&")
+}
+
+// Needs the "Query Ledger Entries" permission of the API key, query filters
+// e.g. "type=staking". Returns at most the newest MAX_PAGES pages after start.
+pub async fn fetch_ledger(
+    client: &PrivateClient,
+    query: &str,
+    start: u64,
+) -> Result<HashMap<String, LedgerEntry>, ApiError> {
+    let mut result = HashMap::new();
+    let mut offset = 0;
+    for _ in 0..MAX_PAGES {
+        let page: LedgerPage = client
+            .send(
+                "/0/private/Ledgers",
+                Some(build_query(query, start, None, offset)),
+            )
+            .await?;
+        let (entries, count) = page.into_entries();
+        let fetched = entries.len();
+        result.extend(entries);
+        offset += fetched;
+        if fetched < PAGE_SIZE || offset >= count {
+            break;
+        }
+    }
+    Ok(result)
+}
+
+// Progress of importing one kind of entries
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct ImportCursor {
+    // All entries up to this unix timestamp are imported, 0 before the first import finished
+    pub complete_until: u64,
+    // An unfinished import, continued by the next one
+    pub resume: Option<Resume>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Resume {
+    // Entries after start are imported
+    pub start: u64,
+    // Kraken returns the newest entries first, the ones up to end (inclusive) are still missing
+    pub end: Option<u64>,
+    // When the import began, complete_until once it finishes
+    pub until: u64,
+}
+
+impl ImportCursor {
+    // Entries of the last second may have been incomplete during the last import
+    fn next_resume(&self, now: u64) -> Resume {
+        match &self.resume {
+            Some(resume) => resume.clone(),
+            None => Resume {
+                start: self.complete_until.saturating_sub(1),
+                end: None,
+                until: now,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct History {
+    // By trade and ledger id
+    pub trades: HashMap<String, Trade>,
+    pub ledger: HashMap<String, LedgerEntry>,
+    #[serde(default)]
+    pub trades_cursor: ImportCursor,
+    #[serde(default)]
+    pub ledger_cursor: ImportCursor,
+}
+
+impl History {
+    // Cost basis is only known once all trades and ledger entries were imported
+    pub fn is_complete(&self) -> bool {
+        self.trades_cursor.complete_until > 0 && self.ledger_cursor.complete_until > 0
+    }
+}
+
+// History per account name
+pub struct LedgerStore {
+    path: Option<PathBuf>,
+    // With the number of changes, each write knows which version it holds
+    histories: Mutex<(HashMap<String, History>, u64)>,
+    // Version in the file, a slower write never replaces a newer one
+    saved: Arc<Mutex<u64>>,
+}
+
+impl LedgerStore {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let histories = match &path {
+            Some(path) if path.exists() => fs::read_to_string(path)
+                .map_err(ApiError::from)
+                .and_then(|content| Ok(serde_json::from_str(&content)?))
+                .unwrap_or_else(|e| {
+                    println!(
+                        "Ignoring Kraken ledger file {}: {}",
+                        path.display(),
+                        e.get_message()
+                    );
+                    HashMap::new()
+                }),
+            _ => HashMap::new(),
+        };
+        Self {
+            path: path,
+            histories: Mutex::new((histories, 0)),
+            saved: Arc::new(Mutex::new(0)),
+        }
+    }
+
+    // Only once the first import finished
+    pub fn get_complete(&self, account: &String) -> Option<History> {
+        let histories = self.histories.lock().unwrap();
+        histories
+            .0
+            .get(account)
+            .filter(|h| h.is_complete())
+            .cloned()
+    }
+
+    fn get_cursor<P: Page>(&self, account: &String) -> ImportCursor {
+        let mut histories = self.histories.lock().unwrap();
+        let history = histories.0.entry(account.clone()).or_default();
+        P::get_part(history).1.clone()
+    }
+
+    fn count_new<P: Page>(&self, account: &String, entries: &HashMap<String, P::Entry>) -> usize {
+        let mut histories = self.histories.lock().unwrap();
+        let history = histories.0.entry(account.clone()).or_default();
+        let known = P::get_part(history).0;
+        entries.keys().filter(|id| !known.contains_key(*id)).count()
+    }
+
+    // Adds the entries of a page with the progress and saves them
+    async fn merge<P: Page>(
+        &self,
+        account: &String,
+        entries: HashMap<String, P::Entry>,
+        cursor: ImportCursor,
+    ) {
+        // Serialized under the lock, written without it
+        let (content, version) = {
+            let mut guard = self.histories.lock().unwrap();
+            let (histories, version) = &mut *guard;
+            let history = histories.entry(account.clone()).or_default();
+            let (known, known_cursor) = P::get_part(history);
+            known.extend(entries);
+            *known_cursor = cursor;
+            *version += 1;
+            (serde_json::to_string(histories), *version)
+        };
+        self.save(content, version).await;
+    }
+
+    async fn save(&self, content: serde_json::Result<String>, version: u64) {
+        let path = match &self.path {
+            Some(p) => p.clone(),
+            None => return,
+        };
+        let write_path = path.clone();
+        let saved = self.saved.clone();
+        let result = match content {
+            Ok(content) => tokio::task::spawn_blocking(move || {
+                let mut saved = saved.lock().unwrap();
+                if *saved < version {
+                    fs::write(write_path, content)?;
+                    *saved = version;
+                }
+                Ok::<(), std::io::Error>(())
+            })
+            .await
+            .map_err(|e| ApiError::new(&e.to_string()))
+            .and_then(|r| Ok(r?)),
+            Err(e) => Err(ApiError::from(e)),
+        };
+        if let Err(e) = result {
+            println!(
+                "Failed to write Kraken ledger file {}: {}",
+                path.display(),
+                e.get_message()
+            );
+        }
+    }
+}
+
+// Imports one kind of entries page by page, newest first. Each page is saved
+// with the progress, so a failed or too long import continues where it stopped.
+async fn import_pages<P: Page>(
+    client: &PrivateClient,
+    store: &LedgerStore,
+    account: &String,
+    path: &str,
+    now: u64,
+) -> Result<(), ApiError> {
+    let mut resume = store.get_cursor::<P>(account).next_resume(now);
+    for _ in 0..MAX_PAGES {
+        let query = build_query("", resume.start, resume.end, 0);
+        let page: P = client.send(path, Some(query)).await?;
+        let (entries, count) = page.into_entries();
+        let fetched = entries.len();
+        let oldest = entries
+            .values()
+            .map(|e| P::get_time(e))
+            .fold(f64::MAX, f64::min);
+        let finished = fetched < PAGE_SIZE || fetched >= count;
+        let mut cursor = store.get_cursor::<P>(account);
+        if finished {
+            cursor.complete_until = resume.until;
+            cursor.resume = None;
+        } else {
+            // end is inclusive, entries of the oldest second are fetched again.
+            // A page of known entries only is skipped by one second.
+            let mut end = oldest.ceil() as u64;
+            if store.count_new::<P>(account, &entries) == 0 {
+                end = end.saturating_sub(1);
+            }
+            resume.end = Some(end);
+            cursor.resume = Some(resume.clone());
+        }
+        store.merge::<P>(account, entries, cursor).await;
+        if finished {
+            return Ok(());
+        }
+    }
+    println!(
+        "Paused importing Kraken {} of {}, continuing with the next import",
+        path, account
+    );
+    Ok(())
+}
+
+// Fetches the entries since the last import, or continues an unfinished one
+pub async fn import(
+    client: &PrivateClient,
+    store: &LedgerStore,
+    account: &String,
+) -> Result<(), ApiError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    import_pages::<TradesPage>(client, store, account, "/0/private/TradesHistory", now).await?;
+    import_pages::<LedgerPage>(client, store, account, "/0/private/Ledgers", now).await
+}
+
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct Position {
+    pub units: f64,
+    // Of the units, in USD
+    pub cost: f64,
+    pub realized: f64,
+    // A trade of the asset could not be valued, so its cost is not known
+    pub unknown: bool,
+}
+
+impl Position {
+    fn acquire(&mut self, units: f64, cost: f64) {
+        self.units += units;
+        self.cost += cost;
+    }
+
+    // Units beyond the known ones have no known cost and are left out
+    fn dispose(&mut self, units: f64, proceeds: Option<f64>) {
+        let covered = units.min(self.units);
+        if covered <= 0. {
+            return;
+        }
+        let cost = self.cost * covered / self.units;
+        if let Some(proceeds) = proceeds {
+            self.realized += proceeds * covered / units - cost;
+        }
+        self.units -= covered;
+        self.cost -= cost;
+    }
+
+    // Share of a balance of units, out of all balances of the asset.
+    // None if the cost is not known.
+    pub fn get_pnl(&self, units: f32, total_units: f32, unit_price: f32) -> Option<ProfitAndLoss> {
+        if self.unknown {
+            return None;
+        }
+        if total_units <= 0. || self.units <= 0. {
+            return Some(ProfitAndLoss::default());
+        }
+        let share = (units / total_units) as f64;
+        let covered = (total_units as f64).min(self.units) * share;
+        let cost_basis = self.cost * covered / self.units;
+        Some(ProfitAndLoss {
+            cost_basis: cost_basis as f32,
+            realized: (self.realized * share) as f32,
+            unrealized: (covered * unit_price as f64 - cost_basis) as f32,
+        })
+    }
+}
+
+fn number(value: &String) -> f64 {
+    value.parse::<f64>().unwrap_or(0.)
+}
+
+enum Event<'a> {
+    Trade(&'a Trade),
+    Entry(&'a LedgerEntry),
+}
+
+// Average cost positions per asset altname. Trades are valued with the USD
+// rate of their quote currency at the time of the trade, given by usd_rate.
+// Without one, both assets of the trade have an unknown cost. Staking and Earn
+// rewards are acquired without cost and withdrawals remove units at their
+// average cost. Deposits have no known cost.
+pub fn get_positions(
+    history: &History,
+    pair_assets: impl Fn(&str) -> Option<(String, String)>,
+    normalize: impl Fn(&str) -> String,
+    usd_rate: impl Fn(&str, f64) -> Option<f64>,
+) -> HashMap<String, Position> {
+    let mut events: Vec<(f64, Event)> = history
+        .trades
+        .values()
+        .map(|t| (t.time, Event::Trade(t)))
+        .chain(history.ledger.values().map(|e| (e.time, Event::Entry(e))))
+        .collect();
+    events.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut positions: HashMap<String, Position> = HashMap::new();
+    for (_, event) in events {
+        match event {
+            Event::Trade(trade) => {
+                let (base, quote) = match pair_assets(&trade.pair) {
+                    Some(assets) => assets,
+                    None => continue,
+                };
+                let rate = match usd_rate(&quote, trade.time) {
+                    Some(rate) => rate,
+                    None => {
+                        positions.entry(base).or_default().unknown = true;
+                        positions.entry(quote).or_default().unknown = true;
+                        continue;
+                    }
+                };
+                let (cost, fee, vol) =
+                    (number(&trade.cost), number(&trade.fee), number(&trade.vol));
+                match trade.kind.as_str() {
+                    "buy" => {
+                        positions
+                            .entry(base)
+                            .or_default()
+                            .acquire(vol, (cost + fee) * rate);
+                        positions
+                            .entry(quote)
+                            .or_default()
+                            .dispose(cost + fee, Some((cost + fee) * rate));
+                    }
+                    "sell" => {
+                        positions
+                            .entry(base)
+                            .or_default()
+                            .dispose(vol, Some((cost - fee) * rate));
+                        positions
+                            .entry(quote)
+                            .or_default()
+                            .acquire(cost - fee, (cost - fee) * rate);
+                    }
+                    _ => (),
+                }
+            }
+            Event::Entry(entry) => {
+                let amount = number(&entry.amount);
+                let position = positions.entry(normalize(&entry.asset)).or_default();
+                if entry.is_reward() {
+                    if amount > 0. {
+                        position.acquire(amount, 0.);
+                    }
+                } else if entry.kind == "withdrawal" {
+                    position.dispose(amount.abs() + number(&entry.fee), None);
+                }
+            }
+        }
+    }
+    positions
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_positions() {
+        let history: History = serde_json::from_value(serde_json::json!({
+            "trades": {
+                "T1": {"pair": "XXBTZEUR", "time": 1.0, "type": "buy", "cost": "9000", "fee": "18", "vol": "1"},
+                "T2": {"pair": "XXBTZEUR", "time": 3.0, "type": "sell", "cost": "5000", "fee": "10", "vol": "0.5"},
+                "T3": {"pair": "UNKNOWN", "time": 4.0, "type": "buy", "cost": "1", "fee": "0", "vol": "1"},
+                "T4": {"pair": "DOTXBT", "time": 8.0, "type": "buy", "cost": "0.01", "fee": "0.0001", "vol": "10"},
+                "T5": {"pair": "ETHGBP", "time": 9.0, "type": "buy", "cost": "1000", "fee": "2", "vol": "1"}
+            },
+            "ledger": {
+                "L1": {"type": "staking", "asset": "DOT.S", "time": 2.0, "amount": "2", "fee": "0", "balance": "12"},
+                "L2": {"type": "withdrawal", "asset": "XXBT", "time": 5.0, "amount": "-0.2499", "fee": "0.0001", "balance": "0.25"},
+                "L3": {"type": "deposit", "asset": "DOT", "time": 0.0, "amount": "10", "fee": "0", "balance": "10"},
+                "L4": {"type": "earn", "subtype": "reward", "asset": "DOT.B", "time": 6.0, "amount": "1", "fee": "0", "balance": "13"},
+                "L5": {"type": "earn", "subtype": "allocation", "asset": "DOT.B", "time": 7.0, "amount": "5", "fee": "0", "balance": "18"}
+            }
+        }))
+        .unwrap();
+        let positions = get_positions(
+            &history,
+            |pair| {
+                let (base, quote) = match pair {
+                    "XXBTZEUR" => ("XBT", "EUR"),
+                    "DOTXBT" => ("DOT", "XBT"),
+                    "ETHGBP" => ("ETH", "GBP"),
+                    _ => return None,
+                };
+                Some((base.to_string(), quote.to_string()))
+            },
+            |asset| match asset {
+                "XXBT" => "XBT".to_string(),
+                "DOT.S" | "DOT.B" => "DOT".to_string(),
+                a => a.to_string(),
+            },
+            // XBT tripled after the DOT was bought, there is no GBP rate
+            |quote, time| match quote {
+                "EUR" => Some(1.25),
+                "XBT" if time < 10. => Some(20000.),
+                "XBT" => Some(60000.),
+                _ => None,
+            },
+        );
+
+        let xbt = positions.get("XBT").unwrap();
+        // Bought for 9018 EUR, sold half for 4990 EUR, withdrew half of the
+        // rest and paid 0.0101 for DOT, worth 202 USD back then
+        let xbt_cost = 9018. * 1.25 / 4.;
+        let paid_cost = xbt_cost * 0.0101 / 0.25;
+        assert!((xbt.units - (0.25 - 0.0101)).abs() < 1e-9);
+        assert!((xbt.cost - (xbt_cost - paid_cost)).abs() < 1e-6);
+        assert!((xbt.realized - ((4990. - 4509.) * 1.25 + 202. - paid_cost)).abs() < 1e-6);
+
+        // The rewards are free, the bought DOT cost what the XBT was worth then
+        let dot = positions.get("DOT").unwrap();
+        assert!((dot.units - 13.).abs() < 1e-9);
+        assert!((dot.cost - 202.).abs() < 1e-6);
+
+        let pnl = xbt.get_pnl(0.5, 0.5, 40000.).unwrap();
+        assert!((pnl.cost_basis as f64 - xbt.cost).abs() < 0.01);
+        assert!((pnl.unrealized as f64 - (xbt.units * 40000. - xbt.cost)).abs() < 0.01);
+        let pnl = dot.get_pnl(13., 13., 20.).unwrap();
+        assert!((pnl.cost_basis - 202.).abs() < 0.01);
+        assert!((pnl.unrealized - 58.).abs() < 0.01);
+
+        // Without a GBP rate neither side of the trade has a known cost
+        assert_eq!(positions.get("ETH").unwrap().get_pnl(1., 1., 3000.), None);
+        assert_eq!(positions.get("GBP").unwrap().get_pnl(1., 1., 1.), None);
+    }
+
+    #[test]
+    fn test_import_cursor() {
+        let mut cursor = ImportCursor::default();
+        let resume = cursor.next_resume(1000);
+        assert_eq!((resume.start, resume.end, resume.until), (0, None, 1000));
+
+        // An import stopped at 500 continues there, not after the newest entry
+        cursor.resume = Some(Resume {
+            start: 0,
+            end: Some(500),
+            until: 1000,
+        });
+        assert_eq!(cursor.next_resume(2000).end, Some(500));
+
+        cursor = ImportCursor {
+            complete_until: 1000,
+            resume: None,
+        };
+        let resume = cursor.next_resume(2000);
+        assert_eq!((resume.start, resume.end, resume.until), (999, None, 2000));
+
+        let mut history = History::default();
+        history.trades_cursor = cursor;
+        assert!(!history.is_complete());
+        history.ledger_cursor.complete_until = 1000;
+        assert!(history.is_complete());
+    }
+
+    #[tokio::test]
+    async fn test_save_order() {
+        let path =
+            std::env::temp_dir().join(format!("get-rich-slow-ledger-{}.json", std::process::id()));
+        let store = LedgerStore::new(Some(path.clone()));
+        let (newer, older) = ("{\"newer\": {}}".to_string(), "{}".to_string());
+        store.save(Ok(newer.clone()), 2).await;
+        // Finished after the newer snapshot, so it is dropped
+        store.save(Ok(older), 1).await;
+        assert_eq!(fs::read_to_string(&path).unwrap(), newer);
+        let _ = fs::remove_file(path);
+    }
+}
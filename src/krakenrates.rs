@@ -0,0 +1,156 @@
+use super::currency::FiatCurrency;
+use super::error::ApiError;
+use super::fx::get_usd_rate_history;
+use kraken_client::{Client, Interval};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+// Historical USD values of the quote currencies of Kraken trades, so that each
+// trade is valued at the rate of its day. Fiat currencies come from the FX
+// source, all others from Kraken's daily OHLC, which covers the last 720 days.
+
+const DAY_SECONDS: f64 = 86400.;
+// Fiat rates are only published on working days
+const MAX_RATE_AGE_DAYS: i64 = 7;
+
+// USD value of one unit per day since 1970-01-01
+pub type RateHistory = BTreeMap<i64, f64>;
+
+pub fn get_day(time: f64) -> i64 {
+    (time / DAY_SECONDS).floor() as i64
+}
+
+// Rate histories per altname, e.g. XBT
+#[derive(Default)]
+pub struct QuoteRates {
+    histories: HashMap<String, RateHistory>,
+}
+
+impl QuoteRates {
+    pub fn insert(&mut self, altname: String, history: RateHistory) {
+        self.histories.insert(altname, history);
+    }
+
+    // Rate of the day of the unix time, or of the last day before it with a rate.
+    // None if there is none, e.g. for trades older than the OHLC data.
+    pub fn get(&self, altname: &str, time: f64) -> Option<f64> {
+        if altname == "USD" {
+            return Some(1.0);
+        }
+        let day = get_day(time);
+        self.histories
+            .get(altname)?
+            .range(day - MAX_RATE_AGE_DAYS..=day)
+            .next_back()
+            .map(|(_, rate)| *rate)
+    }
+}
+
+// Daily volume weighted average prices of a pair, of /0/public/OHLC
+fn parse_ohlc(result: &Value) -> Result<RateHistory, ApiError> {
+    let candles = result
+        .as_object()
+        .and_then(|pairs| pairs.iter().find(|(name, _)| *name != "last"))
+        .and_then(|(_, candles)| candles.as_array());
+    let candles = match candles {
+        Some(c) => c,
+        None => {
+            return Err(ApiError::new(&format!(
+                "Unexpected Kraken OHLC response {}",
+                result
+            )))
+        }
+    };
+    Ok(candles
+        .iter()
+        .filter_map(|candle| {
+            let time = candle.get(0)?.as_f64()?;
+            let price = |i: usize| {
+                let p = candle.get(i)?.as_str()?.parse::<f64>().ok()?;
+                Some(p).filter(|p| *p > 0.)
+            };
+            // Days without trades have no average, only the last close
+            let price = price(5).or_else(|| price(4))?;
+            Some((get_day(time), price))
+        })
+        .collect())
+}
+
+async fn fetch_ohlc(client: &Client, pair: &String) -> Result<RateHistory, ApiError> {
+    let result: Value = client
+        .get_ohlc_data(pair)
+        .interval(Interval::Day1)
+        .execute()
+        .await?;
+    parse_ohlc(&result)
+}
+
+// Rates of a currency since the day. The route holds the pairs to multiply for
+// its USD price, like for the current prices.
+pub async fn fetch_rate_history(
+    client: &Client,
+    altname: &String,
+    since: i64,
+    route: Option<Vec<(String, bool)>>,
+) -> Result<RateHistory, ApiError> {
+    if let Ok(currency) = FiatCurrency::from_code(altname) {
+        return Ok(get_usd_rate_history(&currency, since)
+            .await?
+            .into_iter()
+            .collect());
+    }
+    let route = match route {
+        Some(route) if !route.is_empty() => route,
+        _ => {
+            return Err(ApiError::new(&format!(
+                "No Kraken pair to value {} in USD",
+                altname
+            )))
+        }
+    };
+    let mut rates: Option<RateHistory> = None;
+    for (pair, invert) in route.iter() {
+        let prices = fetch_ohlc(client, pair).await?;
+        let hop = prices
+            .into_iter()
+            .map(|(day, price)| (day, if *invert { 1. / price } else { price }));
+        rates = Some(match rates {
+            None => hop.collect(),
+            // Only days known for every pair of the route
+            Some(rates) => hop
+                .filter_map(|(day, price)| Some((day, rates.get(&day)? * price)))
+                .collect(),
+        });
+    }
+    Ok(rates.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rates() {
+        let history = parse_ohlc(&serde_json::json!({
+            "XXBTZUSD": [
+                [86400, "20000.0", "21000.0", "19000.0", "20500.0", "20100.0", "10.5", 100],
+                [259200, "30000.0", "31000.0", "29000.0", "30500.0", "0.0", "0", 0]
+            ],
+            "last": 259200
+        }))
+        .unwrap();
+        assert_eq!(history.get(&1), Some(&20100.));
+        assert_eq!(history.get(&3), Some(&30500.));
+        assert!(parse_ohlc(&serde_json::json!({"last": 0})).is_err());
+
+        let mut rates = QuoteRates::default();
+        rates.insert("XBT".to_string(), history);
+        assert_eq!(rates.get("USD", 0.), Some(1.));
+        assert_eq!(rates.get("XBT", 0.), None);
+        assert_eq!(rates.get("XBT", 86400. + 3600.), Some(20100.));
+        // The last day with a rate before
+        assert_eq!(rates.get("XBT", 2. * 86400.), Some(20100.));
+        assert_eq!(rates.get("XBT", 20. * 86400.), None);
+        assert_eq!(rates.get("ETH", 86400.), None);
+    }
+}
@@ -0,0 +1,221 @@
+use super::error::ApiError;
+use super::krakenapi::PrivateClient;
+use super::krakenledger::{fetch_ledger, LedgerEntry};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Yearly yields of staked Kraken balances. The realized yield is taken from
+// the staking and Earn rewards of the last year in the ledger, the estimate of
+// the Earn strategies is only used for balances without enough rewards yet.
+
+const YEAR_SECONDS: u64 = 365 * 24 * 3600;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reward {
+    pub asset: String,
+    // Unix timestamp
+    pub time: u64,
+    pub amount: f64,
+    // Staked balance including the reward
+    pub balance: f64,
+}
+
+#[derive(Deserialize)]
+struct StrategyPage {
+    items: Vec<Strategy>,
+}
+
+#[derive(Deserialize)]
+struct Strategy {
+    asset: String,
+    lock_type: LockType,
+    apr_estimate: Option<AprEstimate>,
+}
+
+#[derive(Deserialize)]
+struct LockType {
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+#[derive(Deserialize)]
+struct AprEstimate {
+    // Percent, e.g. "12.0000"
+    low: String,
+}
+
+#[derive(Clone, Default)]
+pub struct StakingYields {
+    // Per ledger asset, which is the balance the rewards were paid to, e.g. DOT.S -> 0.12
+    realized: HashMap<String, f32>,
+    // Per asset altname and lock type, e.g. (DOT, bonded) -> 0.1
+    estimated: HashMap<(String, String), f32>,
+}
+
+impl StakingYields {
+    // The asset codes of strategies are mapped with normalize, e.g. DOT.S -> DOT
+    pub fn new(
+        rewards: &Vec<Reward>,
+        estimates: Vec<(String, String, f32)>,
+        normalize: impl Fn(&str) -> String,
+    ) -> Self {
+        let estimated = estimates
+            .into_iter()
+            .map(|(asset, lock_type, apr)| ((normalize(&asset), lock_type), apr))
+            .collect();
+        Self {
+            realized: realized_yields(rewards),
+            estimated: estimated,
+        }
+    }
+
+    // Yield of a staked balance, e.g. the balance key DOT.S with the altname DOT
+    // and the suffix .S. Balances of one asset with different suffixes are
+    // staked separately, each has its own rewards.
+    pub fn get_yield(&self, key: &String, altname: &String, suffix: &str) -> f32 {
+        if let Some(realized) = self.realized.get(key) {
+            return *realized;
+        }
+        let lock_type = match suffix {
+            ".S" | ".B" => "bonded",
+            ".M" => "instant",
+            _ => "flexible",
+        };
+        if let Some(estimated) = self
+            .estimated
+            .get(&(altname.clone(), lock_type.to_string()))
+        {
+            return *estimated;
+        }
+        // Any strategy of the asset, the lowest estimate
+        self.estimated
+            .iter()
+            .filter(|((asset, _), _)| asset == altname)
+            .map(|(_, apr)| *apr)
+            .fold(None, |min: Option<f32>, apr| {
+                Some(min.map_or(apr, |m| m.min(apr)))
+            })
+            .unwrap_or(0.0)
+    }
+}
+
+// Each reward is relative to the balance it was paid for. The first reward
+// only marks the start, it was earned before the covered period.
+fn realized_yields(rewards: &Vec<Reward>) -> HashMap<String, f32> {
+    let mut per_asset: HashMap<String, Vec<&Reward>> = HashMap::new();
+    for reward in rewards.iter() {
+        per_asset
+            .entry(reward.asset.clone())
+            .or_default()
+            .push(reward);
+    }
+    let mut yields = HashMap::new();
+    for (asset, mut rewards) in per_asset.into_iter() {
+        rewards.sort_by_key(|r| r.time);
+        let (first, last) = match (rewards.first(), rewards.last()) {
+            (Some(first), Some(last)) if last.time > first.time => (first, last),
+            _ => continue,
+        };
+        let rate: f64 = rewards[1..]
+            .iter()
+            .filter(|r| r.balance > r.amount)
+            .map(|r| r.amount / (r.balance - r.amount))
+            .sum();
+        let period = (last.time - first.time) as f64;
+        yields.insert(asset, (rate * YEAR_SECONDS as f64 / period) as f32);
+    }
+    yields
+}
+
+fn parse_rewards(entries: HashMap<String, LedgerEntry>) -> Vec<Reward> {
+    entries
+        .into_values()
+        .filter(|e| e.is_reward())
+        .filter_map(|e| {
+            let amount = e.amount.parse::<f64>().ok()?;
+            let balance = e.balance.parse::<f64>().ok()?;
+            if amount <= 0. || e.time < 0. {
+                return None;
+            }
+            Some(Reward {
+                asset: e.asset,
+                time: e.time as u64,
+                amount: amount,
+                balance: balance,
+            })
+        })
+        .collect()
+}
+
+pub async fn fetch_rewards(client: &PrivateClient) -> Result<Vec<Reward>, ApiError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let start = now.saturating_sub(YEAR_SECONDS);
+    let mut entries = fetch_ledger(client, "type=staking", start).await?;
+    entries.extend(fetch_ledger(client, "type=earn", start).await?);
+    Ok(parse_rewards(entries))
+}
+
+// Estimated yearly yields as (asset, lock type, yield)
+pub async fn fetch_estimates(
+    client: &PrivateClient,
+) -> Result<Vec<(String, String, f32)>, ApiError> {
+    let page: StrategyPage = client.send("/0/private/Earn/Strategies", None).await?;
+    Ok(page
+        .items
+        .into_iter()
+        .filter_map(|s| {
+            let apr = s.apr_estimate?.low.parse::<f32>().ok()?;
+            Some((s.asset, s.lock_type.kind, apr / 100.))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_yields() {
+        let entries: HashMap<String, LedgerEntry> = serde_json::from_value(serde_json::json!({
+            "L1": {"type": "staking", "asset": "DOT.S", "time": 1000.0, "amount": "1.0", "balance": "101.0"},
+            "L2": {"type": "staking", "asset": "DOT.S", "time": 1000.0 + 0.5 * YEAR_SECONDS as f64, "amount": "5.05", "balance": "106.05"},
+            "L3": {"type": "transfer", "asset": "DOT.S", "time": 2000.0, "amount": "100.0", "balance": "100.0"},
+            "L4": {"type": "staking", "asset": "ETH2.S", "time": 3000.0, "amount": "0.01", "balance": "1.01"},
+            "L5": {"type": "earn", "subtype": "reward", "asset": "DOT.B", "time": 1000.0, "amount": "1.0", "balance": "51.0"},
+            "L6": {"type": "earn", "subtype": "reward", "asset": "DOT.B", "time": 1000.0 + 0.5 * YEAR_SECONDS as f64, "amount": "1.0", "balance": "51.0"},
+            "L7": {"type": "earn", "subtype": "allocation", "asset": "DOT.B", "time": 4000.0, "amount": "50.0", "balance": "50.0"}
+        }))
+        .unwrap();
+        let rewards = parse_rewards(entries);
+        assert_eq!(rewards.len(), 5);
+
+        let yields = StakingYields::new(
+            &rewards,
+            vec![
+                ("ETH".to_string(), "bonded".to_string(), 0.04),
+                ("ETH".to_string(), "flexible".to_string(), 0.02),
+            ],
+            |asset| match asset {
+                "DOT.S" => "DOT".to_string(),
+                "ETH2.S" => "ETH".to_string(),
+                a => a.to_string(),
+            },
+        );
+        let get_yield = |key: &str, altname: &str, suffix: &str| {
+            yields.get_yield(&key.to_string(), &altname.to_string(), suffix)
+        };
+        // 5% in half a year
+        assert!((get_yield("DOT.S", "DOT", ".S") - 0.1).abs() < 0.0001);
+        // 2% in half a year, not pooled with DOT.S
+        assert!((get_yield("DOT.B", "DOT", ".B") - 0.04).abs() < 0.0001);
+        // A single reward is not enough, the strategy estimate is used
+        assert_eq!(get_yield("ETH2.S", "ETH", ".S"), 0.04);
+        assert_eq!(get_yield("ETH.F", "ETH", ".F"), 0.02);
+        assert_eq!(get_yield("ETH.M", "ETH", ".M"), 0.02);
+        assert_eq!(get_yield("XBT.M", "XBT", ".M"), 0.0);
+    }
+}
@@ -2,27 +2,55 @@ mod account;
 mod api;
 mod asset;
 mod blockchain;
+mod cache;
 mod config;
 mod cryptoprice;
+mod currency;
+mod date;
 mod domainconfig;
 mod error;
 mod ethereum;
+mod fx;
 mod growth;
+mod history;
+mod jwt;
 mod kraken;
+mod krakenapi;
+mod krakenbalance;
+mod krakenledger;
+mod krakenrates;
+mod krakenstaking;
 mod nordigen;
+mod nordigenquota;
+mod nordigensetup;
+mod portfolio;
+mod provider;
 mod scalable;
+mod scalablegraphql;
+mod totp;
 
 #[macro_use]
 extern crate rocket;
-use rocket::{Build, Rocket};
 
-#[launch]
-fn start() -> Rocket<Build> {
+#[rocket::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(|a| a.as_str()) {
+        None => (),
+        Some("nordigen") => return nordigensetup::run(&args[2..]).await,
+        Some(other) => {
+            println!("Unknown command {}", other);
+            println!("Usage: get-rich-slow [nordigen [setup|list|reauth <account>]]");
+            std::process::exit(1);
+        }
+    }
+
     println!("(Very slow brrrrrr noise)");
 
     let config = config::read_config();
     let domainconfig = domainconfig::DomainConfig::from_config(config);
     println!("Config read and parsed");
 
-    api::get_rocket_build(domainconfig)
+    // Rocket reports launch errors itself when the error is dropped
+    let _ = api::get_rocket_build(domainconfig).launch().await;
 }
@@ -1,201 +1,779 @@
 use super::account::NordigenAccount;
-use super::asset::GenericAsset;
-use super::cryptoprice::get_token_price;
+use super::api::{BalanceDto, TransactionDto};
+use super::asset::{Asset, GenericAsset};
+use super::cache::{CacheSettings, CacheStats, TtlCache};
+use super::config::{expand_home, Account as AccountConfig, Configuration};
+use super::currency::FiatCurrency;
+use super::date::{days_from_civil, parse_date};
+use super::domainconfig::DomainConfig;
 use super::error::ApiError;
+use super::fx::get_fx_rate;
+use super::history::unix_now;
+use super::jwt::Jwt;
+use super::nordigenquota::{QuotaStore, RateLimit};
+use super::provider::{boxed, require, AccountProvider, CredentialExpiry, ProviderMetric};
 use reqwest::Client;
 use serde_json::Value;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::time::{Duration, SystemTime};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-static NORDIGEN_REFRESH: &str = "https://ob.nordigen.com/api/v2/token/refresh/";
-static NORDIGEN_ACCOUNTS: &str = "https://ob.nordigen.com/api/v2/accounts/";
-
-#[derive(Clone)]
-pub enum FiatCurrency {
-    EURO,
-    USD,
-}
-
-impl FiatCurrency {
-    pub fn to_string(&self) -> String {
-        match self {
-            Self::EURO => "EUR",
-            Self::USD => "USD",
-        }
-        .to_string()
-    }
-
-    pub fn from_string(string: String) -> Self {
-        match string.as_str() {
-            "EUR" => Self::EURO,
-            "USD" => Self::USD,
-            x => todo!("Currency {} not supported yet", x),
-        }
-    }
-}
+pub static NORDIGEN_NEW_TOKEN: &str = "https://ob.nordigen.com/api/v2/token/new/";
+pub static NORDIGEN_REFRESH: &str = "https://ob.nordigen.com/api/v2/token/refresh/";
+pub static NORDIGEN_ACCOUNTS: &str = "https://ob.nordigen.com/api/v2/accounts/";
+pub static NORDIGEN_INSTITUTIONS: &str = "https://ob.nordigen.com/api/v2/institutions/";
+pub static NORDIGEN_REQUISITIONS: &str = "https://ob.nordigen.com/api/v2/requisitions/";
+pub static NORDIGEN_AGREEMENTS: &str = "https://ob.nordigen.com/api/v2/agreements/enduser/";
 
 pub struct NordigenCache {
-    pub max_age: Duration,
-    access_token: Arc<Mutex<String>>,
-    balance_table: Arc<Mutex<HashMap<String, (SystemTime, f32, FiatCurrency)>>>,
+    // Access tokens per refresh token
+    access_tokens: TtlCache<String, String>,
+    // Balances per account id, None if Nordigen does not know the account
+    balances: TtlCache<String, Option<Vec<NordigenBalance>>>,
+    // Token and requisition expiry per account id
+    credentials: TtlCache<String, Vec<CredentialExpiry>>,
+    // Transactions per URL, which holds the account id and the dates
+    transactions: TtlCache<String, Vec<TransactionDto>>,
+    // secret_id and secret_key, to get new tokens once the refresh token expired
+    secrets: Option<(String, String)>,
+    quota: QuotaStore,
 }
 
 impl NordigenCache {
-    pub fn new(max_age_hours: u64) -> Self {
+    pub fn new(
+        balance_settings: CacheSettings,
+        secrets: Option<(String, String)>,
+        quota_file: Option<PathBuf>,
+    ) -> Self {
         Self {
-            max_age: Duration::from_secs(max_age_hours * 3600),
-            access_token: Arc::new(Mutex::new(String::new())),
-            balance_table: Arc::new(Mutex::new(HashMap::new())),
+            // Nordigen access tokens are valid for 24 hours
+            access_tokens: TtlCache::new(CacheSettings::new(
+                Duration::from_secs(23 * 3600),
+                Duration::from_secs(0),
+            )),
+            balances: TtlCache::new(balance_settings),
+            // Transactions count against a quota like balances
+            transactions: TtlCache::new(balance_settings),
+            credentials: TtlCache::new(CacheSettings::new(
+                Duration::from_secs(6 * 3600),
+                Duration::from_secs(48 * 3600),
+            )),
+            secrets: secrets,
+            quota: QuotaStore::new(quota_file),
         }
     }
 
-    pub fn get(&self, account_id: &String) -> Option<(f32, FiatCurrency)> {
-        match self.balance_table.lock().unwrap().get(account_id) {
-            None => None,
-            Some((time, balance, currency)) => {
-                if SystemTime::now().duration_since(*time).unwrap() > self.max_age {
-                    None
-                } else {
-                    Some((*balance, (*currency).clone()))
-                }
-            }
-        }
+    pub fn get_stats(&self) -> Vec<(String, CacheStats)> {
+        vec![
+            (
+                "nordigen-tokens".to_string(),
+                self.access_tokens.get_stats(),
+            ),
+            ("nordigen-balances".to_string(), self.balances.get_stats()),
+            (
+                "nordigen-credentials".to_string(),
+                self.credentials.get_stats(),
+            ),
+            (
+                "nordigen-transactions".to_string(),
+                self.transactions.get_stats(),
+            ),
+        ]
     }
 
-    pub fn set(&self, account_id: String, balance: f32, currency: FiatCurrency) {
-        self.balance_table
-            .lock()
-            .unwrap()
-            .insert(account_id, (SystemTime::now(), balance, currency));
+    // Remaining calls per account id and endpoint
+    pub fn get_quota(&self) -> Vec<(String, u32)> {
+        self.quota.get_remaining(unix_now())
     }
+}
 
-    pub fn set_access_token(&self, access_token: String) {
-        *self.access_token.lock().unwrap() = access_token;
-    }
+static NORDIGEN_CACHE: OnceLock<NordigenCache> = OnceLock::new();
 
-    pub fn get_access_token(&self) -> String {
-        self.access_token.lock().unwrap().clone()
-    }
+// Balances are kept for nordigen-cache-hours, three by default
+fn default_balance_settings(hours: Option<u64>) -> CacheSettings {
+    CacheSettings::new(
+        Duration::from_secs(hours.unwrap_or(3) * 3600),
+        Duration::from_secs(24 * 3600),
+    )
+}
+
+fn nordigen_cache() -> &'static NordigenCache {
+    NORDIGEN_CACHE.get_or_init(|| NordigenCache::new(default_balance_settings(None), None, None))
+}
+
+// Remaining calls per account id and endpoint, for the metrics
+// A single balance as reported by the bank, e.g. closingBooked,
+// interimAvailable or expected
+#[derive(Clone)]
+pub struct NordigenBalance {
+    pub balance_type: String,
+    pub amount: f32,
+    pub currency: FiatCurrency,
+    pub reference_date: Option<String>,
 }
 
-enum BalanceAccountResult {
-    Ok((f32, FiatCurrency)),
+enum NordigenResponse {
+    Ok(Value),
     AccessTokenExpired,
+    RateLimited,
 }
 
-async fn get_new_access_token(refresh_token: &String) -> Result<String, ApiError> {
-    println!("Refresh nordigen access token");
-    let client = Client::new();
+// Returns a new access and refresh token
+pub async fn get_new_tokens(
+    secret_id: &String,
+    secret_key: &String,
+) -> Result<(String, String), ApiError> {
+    println!("Create new nordigen tokens");
     let mut map = HashMap::new();
-    map.insert("refresh".to_string(), refresh_token);
-    let result = client
-        .post(NORDIGEN_REFRESH)
+    map.insert("secret_id".to_string(), secret_id);
+    map.insert("secret_key".to_string(), secret_key);
+    let result = Client::new()
+        .post(NORDIGEN_NEW_TOKEN)
         .json(&map)
         .send()
         .await?
         .json::<Value>()
         .await?;
+    match (get_str(&result, "access"), get_str(&result, "refresh")) {
+        (Some(access), Some(refresh)) => Ok((access.to_string(), refresh.to_string())),
+        _ => Err(ApiError::new(&format!(
+            "Failed to create nordigen tokens: {}",
+            result
+        ))),
+    }
+}
+
+// None if Nordigen rejected the refresh token, e.g. because it expired
+async fn refresh_access_token(refresh_token: &String) -> Result<Option<String>, ApiError> {
+    println!("Refresh nordigen access token");
+    let client = Client::new();
+    let mut map = HashMap::new();
+    map.insert("refresh".to_string(), refresh_token);
+    let response = client.post(NORDIGEN_REFRESH).json(&map).send().await?;
+    let status = response.status();
+    if status.is_server_error() {
+        return Err(ApiError::new(&format!(
+            "Nordigen token refresh failed with HTTP {}",
+            status
+        )));
+    }
+    let result = response.json::<Value>().await?;
 
-    match result.get("access") {
+    match get_str(&result, "access") {
+        Some(access) => Ok(Some(access.to_string())),
+        None if status == reqwest::StatusCode::UNAUTHORIZED
+            || status == reqwest::StatusCode::FORBIDDEN =>
+        {
+            println!("Nordigen rejected the refresh token: {}", result);
+            Ok(None)
+        }
         None => Err(ApiError::new(&format!(
-            "Nordigen refresh token expired: {}\n{:?}",
-            refresh_token, result
+            "Unexpected Nordigen token refresh response {}",
+            result
         ))),
-        Some(v) => Ok(v.as_str().unwrap().to_string()),
     }
 }
 
-async fn get_balance_of_account(
+// Falls back to new tokens if the refresh token expired or was rejected and
+// secrets are configured. Other failures, like a network error, are returned.
+async fn get_new_access_token(
+    nordigen_cache: &NordigenCache,
+    refresh_token: &String,
+) -> Result<String, ApiError> {
+    let expired = Jwt::parse(refresh_token)
+        .map(|jwt| jwt.expires_within(Duration::from_secs(60)))
+        .unwrap_or(false);
+    let access_token = if expired {
+        None
+    } else {
+        refresh_access_token(refresh_token).await?
+    };
+    match (access_token, &nordigen_cache.secrets) {
+        (Some(access_token), _) => Ok(access_token),
+        (None, Some((secret_id, secret_key))) => Ok(get_new_tokens(secret_id, secret_key).await?.0),
+        (None, None) => Err(ApiError::new(
+            &"Nordigen refresh token expired, renew it with get-rich-slow nordigen reauth"
+                .to_string(),
+        )),
+    }
+}
+
+async fn get_with_token(
     access_token: &String,
-    account_id: &String,
-) -> Result<BalanceAccountResult, ApiError> {
-    let url = format!("{}{}/balances/", NORDIGEN_ACCOUNTS, account_id);
+    url: &String,
+) -> Result<(NordigenResponse, RateLimit), ApiError> {
     let client = Client::new();
-    let result = client
+    let response = client
         .get(url)
         .header("Authorization", format!("Bearer {}", access_token))
         .send()
-        .await?
-        .json::<Value>()
         .await?;
+    let rate_limit = RateLimit::from_headers(
+        response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| value.to_str().ok().map(|v| (name.as_str(), v))),
+    );
+    if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        return Ok((NordigenResponse::RateLimited, rate_limit));
+    }
+    let result = response.json::<Value>().await?;
 
     if let Some(Value::String(v)) = result.get("summary") {
         if v == "Invalid token" {
-            return Ok(BalanceAccountResult::AccessTokenExpired);
+            return Ok((NordigenResponse::AccessTokenExpired, rate_limit));
         }
     }
+    Ok((NordigenResponse::Ok(result), rate_limit))
+}
 
-    if let Some(Value::Number(v)) = result.get("status_code") {
-        if v.as_i64().unwrap() == 404 {
-            println!("Error! Nordigen Account {} not found!", account_id);
-            return Ok(BalanceAccountResult::Ok((0.0, FiatCurrency::USD)));
+async fn get_access_token(
+    nordigen_cache: &NordigenCache,
+    refresh_token: &String,
+) -> Result<String, ApiError> {
+    nordigen_cache
+        .access_tokens
+        .get_or_fetch(refresh_token.clone(), || {
+            get_new_access_token(nordigen_cache, refresh_token)
+        })
+        .await
+}
+
+// Counts the call against the quota of quota_key, if it has one
+async fn get_counted(
+    nordigen_cache: &NordigenCache,
+    access_token: &String,
+    url: &String,
+    quota_key: Option<&String>,
+) -> Result<NordigenResponse, ApiError> {
+    let (response, rate_limit) = get_with_token(access_token, url).await?;
+    let key = match quota_key {
+        Some(k) => k,
+        None => return Ok(response),
+    };
+    match response {
+        NordigenResponse::RateLimited => {
+            nordigen_cache
+                .quota
+                .record_exhausted(key, &rate_limit, unix_now())
+                .await;
+            Err(ApiError::new(&format!(
+                "Nordigen rate limit of {} reached",
+                key
+            )))
+        }
+        NordigenResponse::Ok(result) => {
+            nordigen_cache
+                .quota
+                .record(key, &rate_limit, unix_now())
+                .await;
+            Ok(NordigenResponse::Ok(result))
         }
+        response => Ok(response),
     }
+}
 
-    let balance_details = result.get("balances").unwrap()[0]
-        .get("balanceAmount")
-        .unwrap();
-    let balance_currency = balance_details.get("currency").unwrap().as_str().unwrap();
-    let balance_amount = balance_details.get("amount").unwrap().as_str().unwrap();
+// GET with the cached access token, refreshing it once if it was rejected.
+// Calls to account data have a daily quota, per account id and endpoint.
+async fn get_authorized(
+    nordigen_cache: &NordigenCache,
+    account: &NordigenAccount,
+    url: &String,
+    quota_key: Option<&String>,
+) -> Result<Value, ApiError> {
+    if let Some(key) = quota_key {
+        nordigen_cache.quota.check(key, unix_now())?;
+    }
+    let access_token = get_access_token(nordigen_cache, &account.refresh_token).await?;
+    if let NordigenResponse::Ok(result) =
+        get_counted(nordigen_cache, &access_token, url, quota_key).await?
+    {
+        return Ok(result);
+    }
+
+    nordigen_cache.access_tokens.remove(&account.refresh_token);
+    let access_token = get_access_token(nordigen_cache, &account.refresh_token).await?;
+    match get_counted(nordigen_cache, &access_token, url, quota_key).await? {
+        NordigenResponse::Ok(result) => Ok(result),
+        _ => Err(ApiError::new(
+            &"Nordigen access token expired immediately".to_string(),
+        )),
+    }
+}
+
+pub fn get_str<'a>(value: &'a Value, field: &str) -> Option<&'a str> {
+    value.get(field).and_then(|v| v.as_str())
+}
+
+// Amounts are sent as strings, e.g. {"amount": "-12.50", "currency": "EUR"}
+fn parse_amount(value: Option<&Value>) -> Result<(f32, FiatCurrency), ApiError> {
+    let (amount, currency) = match value {
+        Some(v) => (get_str(v, "amount"), get_str(v, "currency")),
+        None => (None, None),
+    };
+    match (amount, currency) {
+        (Some(amount), Some(currency)) => {
+            Ok((amount.parse::<f32>()?, FiatCurrency::from_code(currency)?))
+        }
+        _ => Err(ApiError::new(&format!(
+            "Invalid Nordigen amount {:?}",
+            value
+        ))),
+    }
+}
+
+pub fn parse_balances(result: &Value) -> Result<Vec<NordigenBalance>, ApiError> {
+    let balances = match result.get("balances").and_then(|b| b.as_array()) {
+        Some(b) => b,
+        None => {
+            return Err(ApiError::new(&format!(
+                "Unexpected Nordigen balances response {}",
+                result
+            )))
+        }
+    };
+    balances
+        .iter()
+        .map(|b| {
+            let (amount, currency) = parse_amount(b.get("balanceAmount"))?;
+            Ok(NordigenBalance {
+                balance_type: get_str(b, "balanceType").unwrap_or("unknown").to_string(),
+                amount: amount,
+                currency: currency,
+                reference_date: get_str(b, "referenceDate").map(|d| d.to_string()),
+            })
+        })
+        .collect()
+}
+
+// The balance used as the value of the account
+pub fn get_main_balance(balances: &Vec<NordigenBalance>) -> Option<&NordigenBalance> {
+    for balance_type in [
+        "closingBooked",
+        "interimBooked",
+        "interimAvailable",
+        "expected",
+    ] {
+        if let Some(b) = balances.iter().find(|b| b.balance_type == balance_type) {
+            return Some(b);
+        }
+    }
+    balances.first()
+}
+
+async fn fetch_balances(
+    nordigen_cache: &NordigenCache,
+    account: &NordigenAccount,
+) -> Result<Option<Vec<NordigenBalance>>, ApiError> {
+    let url = format!("{}{}/balances/", NORDIGEN_ACCOUNTS, account.account_id);
+    let quota_key = format!("{}/balances", account.account_id);
+    let result = get_authorized(nordigen_cache, account, &url, Some(&quota_key)).await?;
+
+    if let Some(Value::Number(v)) = result.get("status_code") {
+        if v.as_i64() == Some(404) {
+            println!("Nordigen account {} not found", account.account_id);
+            return Ok(None);
+        }
+    }
+    Ok(Some(parse_balances(&result)?))
+}
 
-    Ok(BalanceAccountResult::Ok((
-        balance_amount.parse::<f32>().unwrap(),
-        FiatCurrency::from_string(balance_currency.to_string()),
+// A missing account is an error of its own, not an empty account.
+// The portfolio keeps its last known balance.
+pub async fn get_balances_of_nordigen_account(
+    nordigen_cache: &NordigenCache,
+    account: &NordigenAccount,
+) -> Result<Vec<NordigenBalance>, ApiError> {
+    let balances = nordigen_cache
+        .balances
+        .get_or_fetch(account.account_id.clone(), || {
+            fetch_balances(nordigen_cache, account)
+        })
+        .await?;
+    balances.ok_or(ApiError::unavailable(&format!(
+        "Nordigen account {} not found, the bank connection may have ended. \
+        Run get-rich-slow nordigen reauth {}",
+        account.account_id, account.name
     )))
 }
 
+fn parse_transactions(result: &Value) -> Result<Vec<TransactionDto>, ApiError> {
+    let transactions = match result.get("transactions") {
+        Some(t) => t,
+        None => {
+            return Err(ApiError::new(&format!(
+                "Unexpected Nordigen transactions response {}",
+                result
+            )))
+        }
+    };
+    let mut res = Vec::new();
+    for status in ["booked", "pending"] {
+        let list = match transactions.get(status).and_then(|l| l.as_array()) {
+            Some(l) => l,
+            None => continue,
+        };
+        for t in list.iter() {
+            let (amount, currency) = parse_amount(t.get("transactionAmount"))?;
+            let counterparty = if amount < 0. {
+                get_str(t, "creditorName")
+            } else {
+                get_str(t, "debtorName")
+            };
+            let description = get_str(t, "remittanceInformationUnstructured").or(t
+                .get("remittanceInformationUnstructuredArray")
+                .and_then(|a| a.get(0))
+                .and_then(|d| d.as_str()));
+            res.push(TransactionDto {
+                id: get_str(t, "transactionId").map(|v| v.to_string()),
+                status: status.to_string(),
+                booking_date: get_str(t, "bookingDate").map(|v| v.to_string()),
+                value_date: get_str(t, "valueDate").map(|v| v.to_string()),
+                amount: amount,
+                currency: currency.to_string(),
+                counterparty: counterparty.map(|v| v.to_string()),
+                description: description.map(|v| v.to_string()),
+            });
+        }
+    }
+    Ok(res)
+}
+
+// Dates as YYYY-MM-DD
+pub async fn get_transactions_of_nordigen_account(
+    nordigen_cache: &NordigenCache,
+    account: &NordigenAccount,
+    from: Option<&String>,
+    to: Option<&String>,
+) -> Result<Vec<TransactionDto>, ApiError> {
+    let mut url = format!("{}{}/transactions/", NORDIGEN_ACCOUNTS, account.account_id);
+    let mut query = Vec::new();
+    if let Some(from) = from {
+        query.push(format!("date_from={}", from));
+    }
+    if let Some(to) = to {
+        query.push(format!("date_to={}", to));
+    }
+    if !query.is_empty() {
+        url = format!("{}?{}", url, query.join("&"));
+    }
+    // Served from the cache while the quota is saved
+    nordigen_cache
+        .transactions
+        .get_or_fetch(url.clone(), || async {
+            let quota_key = format!("{}/transactions", account.account_id);
+            let result = get_authorized(nordigen_cache, account, &url, Some(&quota_key)).await?;
+            parse_transactions(&result)
+        })
+        .await
+}
+
+// Nordigen timestamps, e.g. 2022-01-13T10:20:30.123456Z (always UTC)
+fn parse_timestamp(timestamp: &str) -> Option<SystemTime> {
+    let (y, m, d) = parse_date(timestamp.get(0..10)?)?;
+    let mut time = timestamp
+        .get(11..19)
+        .unwrap_or("00:00:00")
+        .split(':')
+        .map(|p| p.parse::<i64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    if !(0..24).contains(&hour) || !(0..60).contains(&minute) || !(0..60).contains(&second) {
+        return None;
+    }
+
+    let days = days_from_civil(y, m, d);
+    let seconds = days * 86400 + hour * 3600 + minute * 60 + second;
+    if seconds < 0 {
+        return None;
+    }
+    Some(UNIX_EPOCH + Duration::from_secs(seconds as u64))
+}
+
+// The requisition (bank consent) the account belongs to
+async fn find_requisition(
+    nordigen_cache: &NordigenCache,
+    account: &NordigenAccount,
+) -> Result<Option<Value>, ApiError> {
+    let mut url = NORDIGEN_REQUISITIONS.to_string();
+    loop {
+        let result = get_authorized(nordigen_cache, account, &url, None).await?;
+        let requisitions = match result.get("results").and_then(|r| r.as_array()) {
+            Some(r) => r,
+            None => {
+                return Err(ApiError::new(&format!(
+                    "Unexpected Nordigen requisitions response {}",
+                    result
+                )))
+            }
+        };
+        let requisition = requisitions.iter().find(|r| {
+            r.get("accounts")
+                .and_then(|a| a.as_array())
+                .map_or(false, |a| {
+                    a.iter()
+                        .any(|id| id.as_str() == Some(account.account_id.as_str()))
+                })
+        });
+        if let Some(r) = requisition {
+            return Ok(Some(r.clone()));
+        }
+        match get_str(&result, "next") {
+            Some(next) => url = next.to_string(),
+            None => return Ok(None),
+        }
+    }
+}
+
+async fn fetch_credential_expiry(
+    nordigen_cache: &NordigenCache,
+    account: &NordigenAccount,
+) -> Result<Vec<CredentialExpiry>, ApiError> {
+    let reauth = format!("run get-rich-slow nordigen reauth {}", account.name);
+    let mut res = Vec::new();
+
+    // With secrets configured, expired refresh tokens are replaced automatically
+    if nordigen_cache.secrets.is_none() {
+        if let Some(expires) = Jwt::parse(&account.refresh_token)
+            .ok()
+            .and_then(|jwt| jwt.get_expiry())
+        {
+            res.push(CredentialExpiry {
+                credential: "refresh-token".to_string(),
+                expires: expires,
+                renew_hint: format!("{} or configure nordigen secrets", reauth),
+            });
+        }
+    }
+
+    let requisition = match find_requisition(nordigen_cache, account).await? {
+        Some(r) => r,
+        None => return Ok(res),
+    };
+    // Access is granted for 90 days, unless the agreement says otherwise
+    let mut valid_days = 90;
+    let mut start = get_str(&requisition, "created").and_then(parse_timestamp);
+    if let Some(agreement) = get_str(&requisition, "agreement") {
+        let url = format!("{}{}/", NORDIGEN_AGREEMENTS, agreement);
+        let result = get_authorized(nordigen_cache, account, &url, None).await?;
+        if let Some(days) = result.get("access_valid_for_days").and_then(|d| d.as_u64()) {
+            valid_days = days;
+        }
+        if let Some(accepted) = get_str(&result, "accepted").and_then(parse_timestamp) {
+            start = Some(accepted);
+        }
+    }
+    let now = SystemTime::now();
+    let expires = start.map(|s| s + Duration::from_secs(valid_days * 86400));
+    let expires = match (get_str(&requisition, "status"), expires) {
+        // EX: expired
+        (Some("EX"), Some(e)) => Some(e.min(now)),
+        (Some("EX"), None) => Some(now),
+        (_, e) => e,
+    };
+    if let Some(expires) = expires {
+        res.push(CredentialExpiry {
+            credential: "requisition".to_string(),
+            expires: expires,
+            renew_hint: reauth,
+        });
+    }
+    Ok(res)
+}
+
+pub async fn get_credential_expiry_of_nordigen_account(
+    nordigen_cache: &NordigenCache,
+    account: &NordigenAccount,
+) -> Result<Vec<CredentialExpiry>, ApiError> {
+    nordigen_cache
+        .credentials
+        .get_or_fetch(account.account_id.clone(), || {
+            fetch_credential_expiry(nordigen_cache, account)
+        })
+        .await
+}
+
 pub async fn get_assets_of_nordigen_account(
     nordigen_cache: &NordigenCache,
     account: &NordigenAccount,
 ) -> Result<Vec<GenericAsset>, ApiError> {
-    let cache_hit = nordigen_cache.get(&account.account_id);
-    if let Some((balance, currency)) = cache_hit {
-        return Ok(vec![GenericAsset::new(
-            0.0,
-            currency.to_string(),
-            account.name.clone(),
-            balance,
-            get_token_price(&"EUR".to_string()).await?,
-        )]);
-    }
-
-    let access_token = if nordigen_cache.get_access_token().is_empty() {
-        get_new_access_token(&account.refresh_token).await?
-    } else {
-        nordigen_cache.get_access_token()
+    let balances = get_balances_of_nordigen_account(nordigen_cache, account).await?;
+    let (balance, currency) = match get_main_balance(&balances) {
+        Some(b) => (b.amount, b.currency),
+        None => {
+            return Err(ApiError::new(&format!(
+                "Nordigen account {} has no balance",
+                account.account_id
+            )))
+        }
     };
 
-    nordigen_cache.set_access_token(access_token.clone());
+    Ok(vec![GenericAsset::new(
+        0.0,
+        currency.to_string(),
+        account.name.clone(),
+        balance,
+        get_fx_rate(&currency, &FiatCurrency::USD).await?,
+    )])
+}
 
-    let balance = get_balance_of_account(&access_token, &account.account_id).await?;
+#[rocket::async_trait]
+impl AccountProvider for NordigenAccount {
+    fn from_config(name: &String, config: &AccountConfig) -> Result<Self, String> {
+        Ok(Self {
+            name: name.clone(),
+            refresh_token: require(&config.refresh_token, "refresh-token", name, "nordigen")?,
+            account_id: require(&config.account_id, "account-id", name, "nordigen")?,
+        })
+    }
 
-    if let BalanceAccountResult::Ok((balance, currency)) = balance {
-        nordigen_cache.set(account.account_id.clone(), balance, currency.clone());
-        return Ok(vec![GenericAsset::new(
-            0.0,
-            currency.to_string(),
-            account.name.clone(),
-            balance,
-            1.19,
-        )]);
+    fn configure(config: &Configuration) {
+        let _ = NORDIGEN_CACHE.set(NordigenCache::new(
+            config.get_cache_settings(
+                "nordigen",
+                default_balance_settings(config.nordigen_cache_hours),
+            ),
+            config
+                .nordigen
+                .as_ref()
+                .map(|n| (n.secret_id.clone(), n.secret_key.clone())),
+            // Remaining Nordigen quota survives restarts
+            Some(PathBuf::from(expand_home(
+                config
+                    .nordigen_quota_file
+                    .as_ref()
+                    .unwrap_or(&"~/.get-rich-slow-nordigen-quota.json".to_string()),
+            ))),
+        ));
     }
 
-    let access_token = get_new_access_token(&account.refresh_token).await?;
-    nordigen_cache.set_access_token(access_token.clone());
+    fn get_cache_stats() -> Vec<(String, CacheStats)> {
+        nordigen_cache().get_stats()
+    }
 
-    let balance = get_balance_of_account(&access_token, &account.account_id).await?;
+    fn get_metrics() -> Vec<ProviderMetric> {
+        nordigen_cache()
+            .get_quota()
+            .into_iter()
+            .map(|(key, remaining)| {
+                let (account_id, endpoint) = key.split_once('/').unwrap_or((&key, ""));
+                ProviderMetric {
+                    name: "nordigen_quota_remaining".to_string(),
+                    help: "Nordigen calls left until the daily quota resets.".to_string(),
+                    labels: vec![
+                        ("account_id".to_string(), account_id.to_string()),
+                        ("endpoint".to_string(), endpoint.to_string()),
+                    ],
+                    value: remaining as f64,
+                }
+            })
+            .collect()
+    }
 
-    if let BalanceAccountResult::Ok((balance, currency)) = balance {
-        nordigen_cache.set(account.account_id.clone(), balance, currency.clone());
-        return Ok(vec![GenericAsset::new(
-            0.0,
-            currency.to_string(),
-            account.name.clone(),
-            balance,
-            1.13, // TODO not hard coded
-        )]);
+    fn get_name(&self) -> String {
+        self.name.clone()
     }
 
-    Ok(vec![])
+    fn get_kind(&self) -> String {
+        "nordigen".to_string()
+    }
+
+    async fn get_assets(
+        &self,
+        _domainconfig: &DomainConfig,
+    ) -> Result<Vec<Box<dyn Asset>>, ApiError> {
+        Ok(boxed(
+            get_assets_of_nordigen_account(nordigen_cache(), self).await?,
+        ))
+    }
+
+    async fn get_balances(
+        &self,
+        _domainconfig: &DomainConfig,
+    ) -> Result<Vec<BalanceDto>, ApiError> {
+        Ok(get_balances_of_nordigen_account(nordigen_cache(), self)
+            .await?
+            .iter()
+            .map(|b| BalanceDto {
+                balance_type: b.balance_type.clone(),
+                amount: b.amount,
+                currency: b.currency.to_string(),
+                reference_date: b.reference_date.clone(),
+            })
+            .collect())
+    }
+
+    async fn get_transactions(
+        &self,
+        _domainconfig: &DomainConfig,
+        from: Option<&String>,
+        to: Option<&String>,
+    ) -> Result<Vec<TransactionDto>, ApiError> {
+        get_transactions_of_nordigen_account(nordigen_cache(), self, from, to).await
+    }
+
+    async fn get_credential_expiry(
+        &self,
+        _domainconfig: &DomainConfig,
+    ) -> Result<Vec<CredentialExpiry>, ApiError> {
+        get_credential_expiry_of_nordigen_account(nordigen_cache(), self).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_balances_and_transactions() {
+        let balances = parse_balances(&json!({"balances": [
+            {"balanceAmount": {"amount": "657.49", "currency": "EUR"},
+             "balanceType": "interimAvailable", "referenceDate": "2022-01-12"},
+            {"balanceAmount": {"amount": "650.00", "currency": "EUR"},
+             "balanceType": "closingBooked", "referenceDate": "2022-01-11"}
+        ]}))
+        .unwrap();
+        assert_eq!(balances.len(), 2);
+        let main = get_main_balance(&balances).unwrap();
+        assert_eq!(main.balance_type, "closingBooked");
+        assert_eq!(main.amount, 650.);
+        assert_eq!(main.currency, FiatCurrency::EUR);
+
+        let transactions = parse_transactions(&json!({"transactions": {
+            "booked": [{"transactionId": "1", "bookingDate": "2022-01-10",
+                "transactionAmount": {"amount": "-45.00", "currency": "EUR"},
+                "creditorName": "Shop", "remittanceInformationUnstructured": "Groceries"}],
+            "pending": [{"transactionAmount": {"amount": "10.00", "currency": "EUR"},
+                "debtorName": "Friend", "valueDate": "2022-01-12"}]
+        }}))
+        .unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].counterparty, Some("Shop".to_string()));
+        assert_eq!(transactions[0].description, Some("Groceries".to_string()));
+        assert_eq!(transactions[1].status, "pending");
+        assert_eq!(transactions[1].counterparty, Some("Friend".to_string()));
+
+        assert!(parse_balances(&json!({"summary": "x"})).is_err());
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        let t = |s| {
+            parse_timestamp(s)
+                .unwrap()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        };
+        assert_eq!(t("1970-01-01T00:00:00Z"), 0);
+        assert_eq!(t("2022-01-13T10:20:30.123456Z"), 1642069230);
+        assert_eq!(t("2024-02-29"), 1709164800);
+        assert!(parse_timestamp("2022-1-13").is_none());
+        assert!(parse_timestamp("2022-13-01").is_none());
+        assert!(parse_timestamp("2023-02-29").is_none());
+        assert!(parse_timestamp("2022-01-13T24:00:00Z").is_none());
+        assert!(parse_timestamp("2022-01-13T10:60:00Z").is_none());
+        assert!(parse_timestamp("2022-01-+1").is_none());
+    }
 }
@@ -0,0 +1,297 @@
+use super::error::ApiError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+// Nordigen allows only a few calls per account and endpoint a day,
+// e.g. 4 balance requests. The remaining quota is read from the response
+// headers and kept in a file, so a restart does not start over with a full budget.
+
+// Stop one call early, the quota of parallel requests is only known afterwards
+const RESERVE: u32 = 1;
+// Assumed if the bank rejected a call without telling when the quota resets
+const DEFAULT_RESET_SECONDS: u64 = 24 * 3600;
+
+#[derive(Default, Debug, PartialEq)]
+pub struct RateLimit {
+    pub limit: Option<u32>,
+    pub remaining: Option<u32>,
+    // Seconds until the quota resets
+    pub reset: Option<u64>,
+}
+
+impl RateLimit {
+    // Accepts X-RateLimit-Account-Success-Remaining as well as the documented
+    // HTTP_X_RATELIMIT_ACCOUNT_SUCCESS_REMAINING. The per account limits win
+    // over the general ones.
+    pub fn from_headers<'a>(headers: impl Iterator<Item = (&'a str, &'a str)>) -> Self {
+        let mut general = RateLimit::default();
+        let mut account = RateLimit::default();
+        for (name, value) in headers {
+            let name = name.to_lowercase().replace('_', "-");
+            let name = name.strip_prefix("http-").unwrap_or(&name);
+            let (limit, field) = match name.strip_prefix("x-ratelimit-account-success-") {
+                Some(field) => (&mut account, field),
+                None => match name.strip_prefix("x-ratelimit-") {
+                    Some(field) => (&mut general, field),
+                    None => continue,
+                },
+            };
+            let value = value.trim();
+            match field {
+                "limit" => limit.limit = value.parse().ok(),
+                "remaining" => limit.remaining = value.parse().ok(),
+                "reset" => limit.reset = value.parse().ok(),
+                _ => (),
+            }
+        }
+        RateLimit {
+            limit: account.limit.or(general.limit),
+            remaining: account.remaining.or(general.remaining),
+            reset: account.reset.or(general.reset),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct Quota {
+    limit: Option<u32>,
+    remaining: u32,
+    // Unix timestamps
+    reset_at: u64,
+    last_request: u64,
+}
+
+impl Quota {
+    // The remaining calls are spread over the time until the reset,
+    // which stretches the refresh interval the closer the limit gets
+    fn next_request(&self) -> u64 {
+        if self.remaining <= RESERVE {
+            return self.reset_at;
+        }
+        let calls = (self.remaining - RESERVE) as u64 + 1;
+        self.last_request + self.reset_at.saturating_sub(self.last_request) / calls
+    }
+}
+
+// Quota per account id and endpoint, e.g. "uuid/balances"
+pub struct QuotaStore {
+    path: Option<PathBuf>,
+    // With the number of changes, each write knows which version it holds
+    quotas: Mutex<(HashMap<String, Quota>, u64)>,
+    // Version in the file, a slower write never replaces a newer one
+    saved: Arc<Mutex<u64>>,
+}
+
+impl QuotaStore {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let quotas = match &path {
+            Some(path) if path.exists() => fs::read_to_string(path)
+                .map_err(ApiError::from)
+                .and_then(|content| Ok(serde_json::from_str(&content)?))
+                .unwrap_or_else(|e| {
+                    println!(
+                        "Ignoring Nordigen quota file {}: {}",
+                        path.display(),
+                        e.get_message()
+                    );
+                    HashMap::new()
+                }),
+            _ => HashMap::new(),
+        };
+        Self {
+            path,
+            quotas: Mutex::new((quotas, 0)),
+            saved: Arc::new(Mutex::new(0)),
+        }
+    }
+
+    // Fails if the call would use up quota needed later on,
+    // the caller serves the last known value instead
+    pub fn check(&self, key: &String, now: u64) -> Result<(), ApiError> {
+        let quotas = self.quotas.lock().unwrap();
+        let quota = match quotas.0.get(key) {
+            Some(q) if now < q.reset_at => q,
+            _ => return Ok(()),
+        };
+        let next_request = quota.next_request();
+        if now >= next_request {
+            return Ok(());
+        }
+        Err(ApiError::new(&format!(
+            "Saving Nordigen quota of {} ({} of {} calls left), next call in {} minutes",
+            key,
+            quota.remaining,
+            quota
+                .limit
+                .map(|l| l.to_string())
+                .unwrap_or("?".to_string()),
+            (next_request - now).div_ceil(60)
+        )))
+    }
+
+    pub async fn record(&self, key: &String, rate_limit: &RateLimit, now: u64) {
+        // Serialized under the lock, written without it
+        let (content, version) = {
+            let mut guard = self.quotas.lock().unwrap();
+            let (quotas, version) = &mut *guard;
+            if !Self::update(quotas, key, rate_limit, now) {
+                return;
+            }
+            *version += 1;
+            (serde_json::to_string(quotas), *version)
+        };
+        self.save(content, version).await;
+    }
+
+    // False if nothing changed
+    fn update(
+        quotas: &mut HashMap<String, Quota>,
+        key: &String,
+        rate_limit: &RateLimit,
+        now: u64,
+    ) -> bool {
+        match rate_limit.remaining {
+            Some(remaining) => {
+                quotas.insert(
+                    key.clone(),
+                    Quota {
+                        limit: rate_limit.limit,
+                        remaining,
+                        reset_at: now + rate_limit.reset.unwrap_or(DEFAULT_RESET_SECONDS),
+                        last_request: now,
+                    },
+                );
+            }
+            // Without headers the quota is unknown, the last call is still counted
+            None => match quotas.get_mut(key) {
+                Some(quota) if now < quota.reset_at => {
+                    quota.remaining = quota.remaining.saturating_sub(1);
+                    quota.last_request = now;
+                }
+                _ => return false,
+            },
+        }
+        true
+    }
+
+    // HTTP 429, nothing left until the reset
+    pub async fn record_exhausted(&self, key: &String, rate_limit: &RateLimit, now: u64) {
+        self.record(
+            key,
+            &RateLimit {
+                limit: rate_limit.limit,
+                remaining: Some(0),
+                reset: rate_limit.reset,
+            },
+            now,
+        )
+        .await;
+    }
+
+    // Remaining calls per key, for the metrics
+    pub fn get_remaining(&self, now: u64) -> Vec<(String, u32)> {
+        let quotas = self.quotas.lock().unwrap();
+        let mut remaining: Vec<(String, u32)> = quotas
+            .0
+            .iter()
+            .filter(|(_, q)| now < q.reset_at)
+            .map(|(k, q)| (k.clone(), q.remaining))
+            .collect();
+        remaining.sort();
+        remaining
+    }
+
+    async fn save(&self, content: serde_json::Result<String>, version: u64) {
+        let path = match &self.path {
+            Some(p) => p.clone(),
+            None => return,
+        };
+        let write_path = path.clone();
+        let saved = self.saved.clone();
+        let result = match content {
+            Ok(content) => tokio::task::spawn_blocking(move || {
+                let mut saved = saved.lock().unwrap();
+                if *saved < version {
+                    fs::write(write_path, content)?;
+                    *saved = version;
+                }
+                Ok::<(), std::io::Error>(())
+            })
+            .await
+            .map_err(|e| ApiError::new(&e.to_string()))
+            .and_then(|r| Ok(r?)),
+            Err(e) => Err(ApiError::from(e)),
+        };
+        if let Err(e) = result {
+            println!(
+                "Failed to write Nordigen quota file {}: {}",
+                path.display(),
+                e.get_message()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_headers() {
+        let headers = vec![
+            ("X-RateLimit-Limit", "100"),
+            ("X-RateLimit-Remaining", "99"),
+            ("HTTP_X_RATELIMIT_ACCOUNT_SUCCESS_LIMIT", "4"),
+            ("HTTP_X_RATELIMIT_ACCOUNT_SUCCESS_REMAINING", "3"),
+            ("x-ratelimit-reset", "60"),
+            ("content-type", "application/json"),
+        ];
+        assert_eq!(
+            RateLimit::from_headers(headers.into_iter()),
+            RateLimit {
+                limit: Some(4),
+                remaining: Some(3),
+                reset: Some(60),
+            }
+        );
+        assert_eq!(
+            RateLimit::from_headers(vec![("server", "nginx")].into_iter()),
+            RateLimit::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_budget() {
+        let path =
+            std::env::temp_dir().join(format!("get-rich-slow-quota-{}.json", std::process::id()));
+        let store = QuotaStore::new(Some(path.clone()));
+        let key = "account/balances".to_string();
+        assert!(store.check(&key, 0).is_ok());
+
+        // 3 of 4 left for the next 24 hours: two calls spread over the day
+        let rate_limit = RateLimit {
+            limit: Some(4),
+            remaining: Some(3),
+            reset: Some(24 * 3600),
+        };
+        store.record(&key, &rate_limit, 0).await;
+        assert!(store.check(&key, 3600).is_err());
+        assert!(store.check(&key, 8 * 3600).is_ok());
+
+        // Known after a restart
+        let store = QuotaStore::new(Some(path.clone()));
+        assert!(store.check(&key, 3600).is_err());
+        assert_eq!(store.get_remaining(3600), vec![(key.clone(), 3)]);
+
+        store
+            .record_exhausted(&key, &RateLimit::default(), 3600)
+            .await;
+        assert!(store.check(&key, 12 * 3600).is_err());
+        assert!(store.check(&key, 28 * 3600).is_ok());
+        assert!(store.get_remaining(28 * 3600).is_empty());
+        let _ = fs::remove_file(path);
+    }
+}
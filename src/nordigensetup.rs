@@ -0,0 +1,695 @@
+use super::config::get_config_path;
+use super::error::ApiError;
+use super::nordigen::{
+    get_main_balance, get_new_tokens, get_str, parse_balances, NORDIGEN_ACCOUNTS,
+    NORDIGEN_INSTITUTIONS, NORDIGEN_REQUISITIONS,
+};
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{stdin, stdout, BufRead, Write};
+use std::sync::OnceLock;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::sync::Mutex;
+
+// Interactive onboarding of bank accounts:
+// get-rich-slow nordigen        link a bank and add its accounts to the config
+// get-rich-slow nordigen list   list all requisitions and their accounts
+// get-rich-slow nordigen reauth <account>
+//                               renew the bank access of an account
+
+// One thread reads stdin for the whole run, a line typed while nobody waits
+// for it is kept for the next read instead of being lost
+fn get_stdin_lines() -> &'static Mutex<UnboundedReceiver<String>> {
+    static LINES: OnceLock<Mutex<UnboundedReceiver<String>>> = OnceLock::new();
+    LINES.get_or_init(|| {
+        let (sender, receiver) = unbounded_channel();
+        std::thread::spawn(move || {
+            for line in stdin().lock().lines() {
+                let sent = match line {
+                    Ok(line) => sender.send(line).is_ok(),
+                    Err(_) => false,
+                };
+                if !sent {
+                    break;
+                }
+            }
+        });
+        Mutex::new(receiver)
+    })
+}
+
+// Empty once stdin is closed
+async fn read_line() -> String {
+    get_stdin_lines()
+        .lock()
+        .await
+        .recv()
+        .await
+        .unwrap_or_default()
+}
+
+async fn prompt(question: &str) -> String {
+    println!("{}", question);
+    print!("> ");
+    let _ = stdout().flush();
+    read_line().await.trim().to_string()
+}
+
+async fn env_or_prompt(variable: &str, question: &str) -> String {
+    match std::env::var(variable) {
+        Ok(v) if !v.is_empty() => v,
+        _ => prompt(question).await,
+    }
+}
+
+fn check_response(result: Value, what: &str) -> Result<Value, ApiError> {
+    match result.get("status_code").and_then(|c| c.as_i64()) {
+        Some(code) if code >= 400 => Err(ApiError::new(&format!(
+            "Nordigen failed to {} ({}): {}",
+            what,
+            code,
+            get_str(&result, "detail").unwrap_or(&result.to_string())
+        ))),
+        _ => Ok(result),
+    }
+}
+
+async fn nordigen_get(access_token: &String, url: &String, what: &str) -> Result<Value, ApiError> {
+    let result = Client::new()
+        .get(url)
+        .header("Authorization", format!("Bearer {}", access_token))
+        .send()
+        .await?
+        .json::<Value>()
+        .await?;
+    check_response(result, what)
+}
+
+async fn nordigen_post(
+    access_token: Option<&String>,
+    url: &str,
+    body: &Value,
+    what: &str,
+) -> Result<Value, ApiError> {
+    let mut request = Client::new().post(url).json(body);
+    if let Some(token) = access_token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+    let result = request.send().await?.json::<Value>().await?;
+    check_response(result, what)
+}
+
+fn read_config_content() -> (String, String) {
+    let path = get_config_path();
+    let content = fs::read_to_string(&path).unwrap_or_default();
+    (path, content)
+}
+
+// Secrets from the config, the environment or asked for
+async fn get_tokens() -> Result<(String, String), ApiError> {
+    let (_, content) = read_config_content();
+    let config: Value = serde_yaml::from_str(&content).unwrap_or(Value::Null);
+    let configured = config.get("nordigen").map(|n| {
+        (
+            get_str(n, "secret-id").map(|s| s.to_string()),
+            get_str(n, "secret-key").map(|s| s.to_string()),
+        )
+    });
+    if let Some((Some(secret_id), Some(secret_key))) = configured {
+        return get_new_tokens(&secret_id, &secret_key).await;
+    }
+
+    println!("Create user secrets at https://nordigen.com (or set NORDIGEN_SECRET_ID");
+    println!("and NORDIGEN_SECRET_KEY).");
+    let secret_id = env_or_prompt("NORDIGEN_SECRET_ID", "Enter the secret_id:").await;
+    let secret_key = env_or_prompt("NORDIGEN_SECRET_KEY", "Enter the secret_key:").await;
+    get_new_tokens(&secret_id, &secret_key).await
+}
+
+// (id, name, bic) of all banks of the country matching the search
+async fn search_institutions(
+    access_token: &String,
+    country_code: &String,
+    search: &String,
+) -> Result<Vec<(String, String, String)>, ApiError> {
+    let url = format!(
+        "{}?country={}",
+        NORDIGEN_INSTITUTIONS,
+        country_code.to_lowercase()
+    );
+    let result = nordigen_get(access_token, &url, "list banks").await?;
+    let banks = match result.as_array() {
+        Some(b) => b,
+        None => {
+            return Err(ApiError::new(&format!(
+                "Unexpected Nordigen institutions response {}",
+                result
+            )))
+        }
+    };
+    let search = search.to_lowercase();
+    Ok(banks
+        .iter()
+        .map(|b| {
+            (
+                get_str(b, "id").unwrap_or_default().to_string(),
+                get_str(b, "name").unwrap_or_default().to_string(),
+                get_str(b, "bic").unwrap_or_default().to_string(),
+            )
+        })
+        .filter(|(id, name, bic)| {
+            !id.is_empty()
+                && (name.to_lowercase().contains(&search)
+                    || bic.to_lowercase().contains(&search)
+                    || id.to_lowercase().contains(&search))
+        })
+        .collect())
+}
+
+// Returns the requisition id and the link the user has to visit
+async fn create_requisition(
+    access_token: &String,
+    institution_id: &String,
+    redirect: &String,
+) -> Result<(String, String), ApiError> {
+    let result = nordigen_post(
+        Some(access_token),
+        NORDIGEN_REQUISITIONS,
+        &json!({"redirect": redirect, "institution_id": institution_id}),
+        "create a requisition",
+    )
+    .await?;
+    match (get_str(&result, "id"), get_str(&result, "link")) {
+        (Some(id), Some(link)) => Ok((id.to_string(), link.to_string())),
+        _ => Err(ApiError::new(&format!(
+            "Unexpected Nordigen requisition response {}",
+            result
+        ))),
+    }
+}
+
+// Nordigen redirects the browser here after the bank login
+async fn wait_for_callback(listener: TcpListener) -> Result<(), ApiError> {
+    let (mut socket, _) = listener.accept().await?;
+    let mut buffer = vec![0; 4096];
+    let _ = socket.read(&mut buffer).await?;
+    let body = "Bank linked. You can close this window and return to get-rich-slow.";
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    socket.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+// Returns institution id, status and account ids
+async fn get_requisition(
+    access_token: &String,
+    requisition_id: &String,
+) -> Result<(String, String, Vec<String>), ApiError> {
+    let url = format!("{}{}/", NORDIGEN_REQUISITIONS, requisition_id);
+    let result = nordigen_get(access_token, &url, "get the requisition").await?;
+    let accounts = result
+        .get("accounts")
+        .and_then(|a| a.as_array())
+        .map(|a| {
+            a.iter()
+                .filter_map(|id| id.as_str().map(|id| id.to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok((
+        get_str(&result, "institution_id")
+            .unwrap_or_default()
+            .to_string(),
+        get_str(&result, "status").unwrap_or_default().to_string(),
+        accounts,
+    ))
+}
+
+async fn describe_account(access_token: &String, account_id: &String) -> String {
+    let url = format!("{}{}/balances/", NORDIGEN_ACCOUNTS, account_id);
+    let balance = match nordigen_get(access_token, &url, "get the balance").await {
+        Ok(result) => match parse_balances(&result) {
+            Ok(balances) => match get_main_balance(&balances) {
+                Some(b) => format!("{} {}", b.amount, b.currency.to_string()),
+                None => "no balance".to_string(),
+            },
+            Err(e) => e.get_message(),
+        },
+        Err(e) => e.get_message(),
+    };
+    let url = format!("{}{}/details/", NORDIGEN_ACCOUNTS, account_id);
+    let iban = match nordigen_get(access_token, &url, "get the details").await {
+        Ok(result) => result
+            .get("account")
+            .and_then(|a| get_str(a, "iban"))
+            .unwrap_or("unknown IBAN")
+            .to_string(),
+        Err(_) => "unknown IBAN".to_string(),
+    };
+    format!("{} (Balance {})", iban, balance)
+}
+
+fn get_existing_account_names(content: &String) -> Vec<String> {
+    let config: Value = serde_yaml::from_str(content).unwrap_or(Value::Null);
+    match config.get("accounts").and_then(|a| a.as_object()) {
+        Some(accounts) => accounts.keys().cloned().collect(),
+        None => Vec::new(),
+    }
+}
+
+fn format_account(
+    indent: &str,
+    name: &String,
+    refresh_token: &String,
+    account_id: &String,
+) -> String {
+    format!(
+        "{i}{}:\n{i}{i}kind: nordigen\n{i}{i}refresh-token: {}\n{i}{i}account-id: {}\n",
+        name,
+        refresh_token,
+        account_id,
+        i = indent
+    )
+}
+
+// Adds the accounts right below "accounts:" as plain text,
+// so comments and formatting of the config are kept
+fn insert_accounts(
+    content: &String,
+    refresh_token: &String,
+    accounts: &Vec<(String, String)>,
+) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let position = lines
+        .iter()
+        .position(|l| l.trim_end() == "accounts:" || l.starts_with("accounts: "));
+    let indent: String = match position {
+        Some(p) => lines[p + 1..]
+            .iter()
+            .find(|l| !l.trim().is_empty() && !l.trim_start().starts_with('#'))
+            .map(|l| l.chars().take_while(|c| *c == ' ').collect())
+            .filter(|i: &String| !i.is_empty())
+            .unwrap_or("    ".to_string()),
+        None => "    ".to_string(),
+    };
+    let block: String = accounts
+        .iter()
+        .map(|(name, id)| format_account(&indent, name, refresh_token, id))
+        .collect();
+
+    match position {
+        None => {
+            let mut result = content.clone();
+            if !result.is_empty() && !result.ends_with('\n') {
+                result.push('\n');
+            }
+            result.push_str("accounts:\n");
+            result.push_str(&block);
+            result
+        }
+        Some(p) => {
+            let mut result: String = lines[..p + 1].iter().map(|l| format!("{}\n", l)).collect();
+            result.push_str(&block);
+            result.push_str(
+                &lines[p + 1..]
+                    .iter()
+                    .map(|l| format!("{}\n", l))
+                    .collect::<String>(),
+            );
+            result
+        }
+    }
+}
+
+// Returns id and name of the bank
+async fn choose_institution(access_token: &String) -> Result<(String, String), ApiError> {
+    let country_code = prompt("Enter the country code of your bank, e.g. DE:").await;
+    let (institution_id, institution_name) = loop {
+        let search = prompt("Search your bank by name or BIC:").await;
+        let banks = search_institutions(access_token, &country_code, &search).await?;
+        if banks.is_empty() {
+            println!("No bank found, try again");
+            continue;
+        }
+        for (i, (_, name, bic)) in banks.iter().enumerate() {
+            println!("{:>3}: {} ({})", i + 1, name, bic);
+        }
+        let choice = prompt("Enter the number of your bank (empty to search again):").await;
+        if let Ok(i) = choice.parse::<usize>() {
+            if i >= 1 && i <= banks.len() {
+                break (banks[i - 1].0.clone(), banks[i - 1].1.clone());
+            }
+        }
+    };
+    Ok((institution_id, institution_name))
+}
+
+// Lets the user log in to the bank, returns the ids of the accounts granted access to
+async fn link_bank(
+    access_token: &String,
+    institution_id: &String,
+    institution_name: &String,
+) -> Result<Vec<String>, ApiError> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let redirect = format!("http://{}/", listener.local_addr()?);
+    let (requisition_id, link) =
+        create_requisition(access_token, institution_id, &redirect).await?;
+    println!(
+        "Log in to {} and grant access:\n\t{}",
+        institution_name, link
+    );
+    println!("Waiting for the redirect to {}", redirect);
+    println!("(If your browser runs on another machine, press enter after the login)");
+    tokio::select! {
+        result = wait_for_callback(listener) => result?,
+        _ = read_line() => (),
+    }
+
+    let (_, status, account_ids) = get_requisition(access_token, &requisition_id).await?;
+    if account_ids.is_empty() {
+        return Err(ApiError::new(&format!(
+            "Requisition {} has no accounts (status {}), was the login completed?",
+            requisition_id, status
+        )));
+    }
+    Ok(account_ids)
+}
+
+async fn setup() -> Result<(), ApiError> {
+    let (access_token, refresh_token) = get_tokens().await?;
+    let (institution_id, institution_name) = choose_institution(&access_token).await?;
+    let account_ids = link_bank(&access_token, &institution_id, &institution_name).await?;
+
+    let (path, content) = read_config_content();
+    let mut taken = get_existing_account_names(&content);
+    let mut accounts = Vec::new();
+    for (i, account_id) in account_ids.iter().enumerate() {
+        println!(
+            "Account {}: {}",
+            account_id,
+            describe_account(&access_token, account_id).await
+        );
+        let default_name = format!("{}-{}", institution_id.to_lowercase(), i + 1);
+        let name = loop {
+            let name = prompt(&format!(
+                "Name for this account (empty for {}, - to skip):",
+                default_name
+            ))
+            .await;
+            let name = if name.is_empty() {
+                default_name.clone()
+            } else {
+                name
+            };
+            if name == "-" || !taken.contains(&name) {
+                break name;
+            }
+            println!("Account {} already exists in the config", name);
+        };
+        if name != "-" {
+            taken.push(name.clone());
+            accounts.push((name, account_id.clone()));
+        }
+    }
+    if accounts.is_empty() {
+        println!("No accounts added");
+        return Ok(());
+    }
+
+    fs::write(&path, insert_accounts(&content, &refresh_token, &accounts))?;
+    println!("Added {} account(s) to {}", accounts.len(), path);
+    println!("Add them to a fund to see them in the overview.");
+    Ok(())
+}
+
+// Replaces the account-id and refresh-token in the block of the named account
+// only, as plain text like insert_accounts. Other accounts with the same
+// values and comments mentioning them are kept.
+fn replace_credentials(
+    content: &String,
+    name: &String,
+    new_account_id: &String,
+    new_refresh_token: &String,
+) -> Result<String, ApiError> {
+    let mut lines: Vec<String> = content.lines().map(|l| l.to_string()).collect();
+    let indent_of = |l: &String| l.len() - l.trim_start().len();
+    let is_entry = |l: &String| !l.trim().is_empty() && !l.trim_start().starts_with('#');
+    let not_found = || ApiError::new(&format!("Account {} not found in the config", name));
+
+    let accounts = lines
+        .iter()
+        .position(|l| l.trim_end() == "accounts:")
+        .ok_or_else(not_found)?;
+    let indent = lines[accounts + 1..]
+        .iter()
+        .find(|l| is_entry(l))
+        .map(indent_of)
+        .filter(|i| *i > 0)
+        .ok_or_else(not_found)?;
+    let mut block = None;
+    for (i, line) in lines.iter().enumerate().skip(accounts + 1) {
+        if !is_entry(line) {
+            continue;
+        }
+        if indent_of(line) < indent {
+            break;
+        }
+        let key = line
+            .trim()
+            .trim_end_matches(':')
+            .trim_matches(|c| c == '"' || c == '\'');
+        if indent_of(line) == indent && line.trim_end().ends_with(':') && key == name {
+            block = Some(i);
+            break;
+        }
+    }
+    let start = block.ok_or_else(not_found)?;
+
+    let mut replaced = (false, false);
+    for line in lines.iter_mut().skip(start + 1) {
+        if is_entry(line) && indent_of(line) <= indent {
+            break;
+        }
+        let prefix = line[..indent_of(line)].to_string();
+        if line.trim_start().starts_with("account-id:") {
+            *line = format!("{}account-id: {}", prefix, new_account_id);
+            replaced.0 = true;
+        } else if line.trim_start().starts_with("refresh-token:") {
+            *line = format!("{}refresh-token: {}", prefix, new_refresh_token);
+            replaced.1 = true;
+        }
+    }
+    if replaced != (true, true) {
+        return Err(ApiError::new(&format!(
+            "Account {} has no account-id or refresh-token",
+            name
+        )));
+    }
+    let mut result = lines.join("\n");
+    if content.ends_with('\n') {
+        result.push('\n');
+    }
+    Ok(result)
+}
+
+async fn get_iban(access_token: &String, account_id: &String) -> Option<String> {
+    let url = format!("{}{}/details/", NORDIGEN_ACCOUNTS, account_id);
+    let result = nordigen_get(access_token, &url, "get the details")
+        .await
+        .ok()?;
+    result
+        .get("account")
+        .and_then(|a| get_str(a, "iban"))
+        .map(|iban| iban.to_string())
+}
+
+async fn reauth(name: &String) -> Result<(), ApiError> {
+    let (path, content) = read_config_content();
+    let config: Value = serde_yaml::from_str(&content).unwrap_or(Value::Null);
+    let account = match config.get("accounts").and_then(|a| a.get(name)) {
+        Some(a) if get_str(a, "kind") == Some("nordigen") => a,
+        _ => {
+            return Err(ApiError::new(&format!(
+                "No nordigen account {} in {}",
+                name, path
+            )))
+        }
+    };
+    // Both are replaced once the bank is linked again
+    let old_account_id = match (
+        get_str(account, "account-id"),
+        get_str(account, "refresh-token"),
+    ) {
+        (Some(id), Some(_)) => id.to_string(),
+        _ => {
+            return Err(ApiError::new(&format!(
+                "Account {} has no account-id or refresh-token",
+                name
+            )))
+        }
+    };
+
+    let (access_token, refresh_token) = get_tokens().await?;
+    let url = format!("{}{}/", NORDIGEN_ACCOUNTS, old_account_id);
+    let institution = match nordigen_get(&access_token, &url, "get the account").await {
+        Ok(result) => get_str(&result, "institution_id").map(|i| (i.to_string(), i.to_string())),
+        Err(_) => None,
+    };
+    let (institution_id, institution_name) = match institution {
+        Some(i) => i,
+        None => choose_institution(&access_token).await?,
+    };
+    let old_iban = get_iban(&access_token, &old_account_id).await;
+
+    let account_ids = link_bank(&access_token, &institution_id, &institution_name).await?;
+    let mut new_account_id = None;
+    for account_id in account_ids.iter() {
+        if old_iban.is_some() && get_iban(&access_token, account_id).await == old_iban {
+            new_account_id = Some(account_id.clone());
+        }
+    }
+    let new_account_id = match new_account_id {
+        Some(id) => id,
+        None if account_ids.len() == 1 => account_ids[0].clone(),
+        None => loop {
+            for (i, account_id) in account_ids.iter().enumerate() {
+                println!(
+                    "{:>3}: {}",
+                    i + 1,
+                    describe_account(&access_token, account_id).await
+                );
+            }
+            let choice = prompt(&format!("Enter the number of the account {}:", name)).await;
+            if let Ok(i) = choice.parse::<usize>() {
+                if i >= 1 && i <= account_ids.len() {
+                    break account_ids[i - 1].clone();
+                }
+            }
+        },
+    };
+
+    fs::write(
+        &path,
+        replace_credentials(&content, name, &new_account_id, &refresh_token)?,
+    )?;
+    println!("Updated account {} in {}", name, path);
+    println!("Restart get-rich-slow to use the new credentials.");
+    Ok(())
+}
+
+async fn list_requisitions() -> Result<(), ApiError> {
+    let (access_token, _) = get_tokens().await?;
+    let result = nordigen_get(
+        &access_token,
+        &NORDIGEN_REQUISITIONS.to_string(),
+        "list requisitions",
+    )
+    .await?;
+    let requisitions = result
+        .get("results")
+        .and_then(|r| r.as_array())
+        .cloned()
+        .unwrap_or_default();
+    let mut descriptions: HashMap<String, String> = HashMap::new();
+    for requisition in requisitions.iter() {
+        let accounts: Vec<String> = requisition
+            .get("accounts")
+            .and_then(|a| a.as_array())
+            .map(|a| {
+                a.iter()
+                    .filter_map(|id| id.as_str().map(|s| s.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        println!(
+            "{} {} ({}, {} accounts):",
+            get_str(requisition, "institution_id").unwrap_or_default(),
+            get_str(requisition, "id").unwrap_or_default(),
+            get_str(requisition, "status").unwrap_or_default(),
+            accounts.len()
+        );
+        for account_id in accounts {
+            if !descriptions.contains_key(&account_id) {
+                let description = describe_account(&access_token, &account_id).await;
+                descriptions.insert(account_id.clone(), description);
+            }
+            println!("\t{} {}", account_id, descriptions[&account_id]);
+        }
+    }
+    Ok(())
+}
+
+pub async fn run(args: &[String]) {
+    let result = match args.first().map(|a| a.as_str()) {
+        None | Some("setup") => setup().await,
+        Some("list") => list_requisitions().await,
+        Some("reauth") if args.len() == 2 => reauth(&args[1]).await,
+        Some(other) => {
+            println!("Unknown command {}", other);
+            println!("Usage: get-rich-slow nordigen [setup|list|reauth <account>]");
+            return;
+        }
+    };
+    if let Err(e) = result {
+        println!("Error: {}", e.get_message());
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_insert_accounts() {
+        let content =
+            "# My config\naccounts:\n  # Wallets\n  bsc-wallet:\n    kind: bsc\nfunds: []\n"
+                .to_string();
+        let accounts = vec![("bank-1".to_string(), "abc".to_string())];
+        let result = insert_accounts(&content, &"token".to_string(), &accounts);
+        assert_eq!(
+            result,
+            "# My config\naccounts:\n  bank-1:\n    kind: nordigen\n    refresh-token: token\n    account-id: abc\n  # Wallets\n  bsc-wallet:\n    kind: bsc\nfunds: []\n"
+        );
+        assert_eq!(
+            get_existing_account_names(&result),
+            vec!["bank-1".to_string(), "bsc-wallet".to_string()]
+        );
+
+        let result = insert_accounts(&"port: 8080".to_string(), &"token".to_string(), &accounts);
+        assert!(result.starts_with("port: 8080\naccounts:\n    bank-1:\n"));
+    }
+
+    #[test]
+    fn test_replace_credentials() {
+        // Both accounts of one bank consent, with the same token
+        let content = "accounts:\n  # old-id is the savings account\n  a:\n    kind: nordigen\n    account-id: old-id\n    refresh-token: old-token\n  b:\n    kind: nordigen\n    account-id: old-id\n    refresh-token: old-token\nfunds: []\n".to_string();
+        let result = replace_credentials(
+            &content,
+            &"b".to_string(),
+            &"new-id".to_string(),
+            &"new-token".to_string(),
+        );
+        assert_eq!(
+            result.unwrap(),
+            "accounts:\n  # old-id is the savings account\n  a:\n    kind: nordigen\n    account-id: old-id\n    refresh-token: old-token\n  b:\n    kind: nordigen\n    account-id: new-id\n    refresh-token: new-token\nfunds: []\n"
+        );
+        let replace = |name: &str| {
+            replace_credentials(
+                &content,
+                &name.to_string(),
+                &"x".to_string(),
+                &"y".to_string(),
+            )
+        };
+        assert!(replace("kind").is_err());
+        assert!(replace("c").is_err());
+    }
+}
@@ -0,0 +1,253 @@
+use super::api::{AccountState, AccountStatusDto, AssetDto};
+use super::currency::FiatCurrency;
+use super::domainconfig::DomainConfig;
+use super::error::ApiError;
+use super::provider::{AccountProvider, CredentialExpiry};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::sync::Semaphore;
+use tokio::time::{timeout, MissedTickBehavior};
+
+// A provider that hangs must not keep its account, and the permit, forever
+const REFRESH_TIMEOUT: Duration = Duration::from_secs(300);
+
+// Latest known state of every account. Filled by the background
+// refresh tasks, read by the routes without waiting for any provider.
+
+#[derive(Clone)]
+pub struct AccountSnapshot {
+    pub kind: String,
+    pub state: AccountState,
+    pub message: Option<String>,
+    // Time of the last successful fetch, the assets are from then
+    pub fetched: Option<SystemTime>,
+    pub assets: Vec<AssetDto>,
+    pub expiries: Vec<CredentialExpiry>,
+}
+
+impl AccountSnapshot {
+    pub fn get_age(&self) -> Option<Duration> {
+        self.fetched
+            .map(|t| SystemTime::now().duration_since(t).unwrap_or_default())
+    }
+
+    // Negative once expired
+    pub fn get_expiry_seconds(expiry: &CredentialExpiry) -> i64 {
+        match expiry.expires.duration_since(SystemTime::now()) {
+            Ok(d) => d.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        }
+    }
+
+    pub fn get_expiry_warnings(&self, name: &String, warn_before: Duration) -> Vec<String> {
+        self.expiries
+            .iter()
+            .filter_map(|e| {
+                let seconds = Self::get_expiry_seconds(e);
+                let days = seconds.abs() / 86400;
+                if seconds <= 0 {
+                    Some(format!(
+                        "Account {} ({}): {} expired {} days ago, {}",
+                        name, self.kind, e.credential, days, e.renew_hint
+                    ))
+                } else if seconds as u64 <= warn_before.as_secs() {
+                    Some(format!(
+                        "Account {} ({}): {} expires in {} days, {}",
+                        name, self.kind, e.credential, days, e.renew_hint
+                    ))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn to_status_dto(&self, name: &str) -> AccountStatusDto {
+        AccountStatusDto {
+            name: name.to_string(),
+            kind: self.kind.clone(),
+            status: self.state.clone(),
+            message: self.message.clone(),
+            age_seconds: self.get_age().map(|a| a.as_secs()),
+            expires_in_days: self
+                .expiries
+                .iter()
+                .map(|e| Self::get_expiry_seconds(e) / 86400)
+                .min(),
+        }
+    }
+}
+
+pub struct PortfolioCache {
+    accounts: RwLock<HashMap<String, AccountSnapshot>>,
+    // Last exchange rate from USD into each currency the overview was shown in
+    rates: RwLock<HashMap<FiatCurrency, f32>>,
+}
+
+impl PortfolioCache {
+    pub fn new() -> Self {
+        Self {
+            accounts: RwLock::new(HashMap::new()),
+            rates: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn get_last_rate(&self, currency: &FiatCurrency) -> Option<f32> {
+        self.rates.read().unwrap().get(currency).copied()
+    }
+
+    pub fn set_last_rate(&self, currency: &FiatCurrency, rate: f32) {
+        self.rates.write().unwrap().insert(*currency, rate);
+    }
+
+    pub fn get(&self, account: &Arc<dyn AccountProvider>) -> AccountSnapshot {
+        match self.accounts.read().unwrap().get(&account.get_name()) {
+            Some(snapshot) => snapshot.clone(),
+            None => AccountSnapshot {
+                kind: account.get_kind(),
+                state: AccountState::Pending,
+                message: Some(format!(
+                    "Account {} ({}): not fetched yet",
+                    account.get_name(),
+                    account.get_kind()
+                )),
+                fetched: None,
+                assets: Vec::new(),
+                expiries: Vec::new(),
+            },
+        }
+    }
+
+    pub fn update(
+        &self,
+        account: &Arc<dyn AccountProvider>,
+        result: Result<Vec<AssetDto>, ApiError>,
+    ) {
+        let name = account.get_name();
+        let kind = account.get_kind();
+        let mut accounts = self.accounts.write().unwrap();
+        let previous = accounts.remove(&name);
+        let expiries = previous
+            .as_ref()
+            .map(|p| p.expiries.clone())
+            .unwrap_or_default();
+        let snapshot = match (result, previous) {
+            (Ok(assets), _) => AccountSnapshot {
+                kind,
+                state: AccountState::Ok,
+                message: None,
+                fetched: Some(SystemTime::now()),
+                assets,
+                expiries,
+            },
+            // Keep serving the assets of the last successful fetch
+            (Err(e), Some(previous)) if previous.fetched.is_some() => AccountSnapshot {
+                kind: kind.clone(),
+                state: if e.is_unavailable() {
+                    AccountState::Unavailable
+                } else if e.is_pending() {
+                    AccountState::Pending
+                } else {
+                    AccountState::Stale
+                },
+                message: Some(e.for_account(&name, &kind).get_message()),
+                fetched: previous.fetched,
+                assets: previous
+                    .assets
+                    .into_iter()
+                    .map(|a| AssetDto { stale: true, ..a })
+                    .collect(),
+                expiries,
+            },
+            (Err(e), _) => AccountSnapshot {
+                kind: kind.clone(),
+                state: if e.is_unavailable() {
+                    AccountState::Unavailable
+                } else if e.is_pending() {
+                    AccountState::Pending
+                } else {
+                    AccountState::Error
+                },
+                message: Some(e.for_account(&name, &kind).get_message()),
+                fetched: None,
+                assets: Vec::new(),
+                expiries,
+            },
+        };
+        accounts.insert(name, snapshot);
+    }
+
+    pub fn update_expiries(
+        &self,
+        account: &Arc<dyn AccountProvider>,
+        expiries: Vec<CredentialExpiry>,
+    ) {
+        if let Some(snapshot) = self.accounts.write().unwrap().get_mut(&account.get_name()) {
+            snapshot.expiries = expiries;
+        }
+    }
+}
+
+pub async fn refresh_account(
+    domainconfig: &DomainConfig,
+    cache: &PortfolioCache,
+    account: &Arc<dyn AccountProvider>,
+) {
+    let result = match timeout(REFRESH_TIMEOUT, account.get_assets(domainconfig)).await {
+        Ok(result) => result.map(|assets| {
+            assets
+                .iter()
+                .map(Box::as_ref)
+                .map(AssetDto::from_asset)
+                .collect()
+        }),
+        Err(_) => Err(ApiError::new(&format!(
+            "fetch timed out after {} seconds",
+            REFRESH_TIMEOUT.as_secs()
+        ))),
+    };
+    cache.update(account, result);
+
+    let expiries = timeout(REFRESH_TIMEOUT, account.get_credential_expiry(domainconfig))
+        .await
+        .unwrap_or_else(|_| Err(ApiError::new(&"timed out".to_string())));
+    match expiries {
+        Ok(expiries) => cache.update_expiries(account, expiries),
+        Err(e) => println!(
+            "Failed to check credentials of account {}: {}",
+            account.get_name(),
+            e.get_message()
+        ),
+    }
+}
+
+async fn refresh_loop(
+    domainconfig: Arc<DomainConfig>,
+    cache: Arc<PortfolioCache>,
+    semaphore: Arc<Semaphore>,
+    account: Arc<dyn AccountProvider>,
+) {
+    let mut interval =
+        tokio::time::interval(domainconfig.get_refresh_interval(&account.get_kind()));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        // Limits how many providers are queried at the same time
+        let _permit = semaphore.acquire().await.unwrap();
+        refresh_account(&domainconfig, &cache, &account).await;
+    }
+}
+
+// One task per account, even if the account is part of multiple funds
+pub fn spawn_refresh_tasks(domainconfig: Arc<DomainConfig>, cache: Arc<PortfolioCache>) {
+    let semaphore = Arc::new(Semaphore::new(domainconfig.max_concurrent_requests));
+    for account in domainconfig.get_fund_accounts() {
+        tokio::spawn(refresh_loop(
+            domainconfig.clone(),
+            cache.clone(),
+            semaphore.clone(),
+            account,
+        ));
+    }
+}
@@ -0,0 +1,254 @@
+use super::account::{EthereumAccount, KrakenAccount, NordigenAccount, ScalableAccount};
+use super::api::{BalanceDto, TransactionDto};
+use super::asset::Asset;
+use super::cache::CacheStats;
+use super::config::{Account as AccountConfig, Configuration};
+use super::domainconfig::DomainConfig;
+use super::error::ApiError;
+use super::ethereum::EthereumChain;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+// When credentials (tokens, bank consents, ...) stop working
+#[derive(Clone)]
+pub struct CredentialExpiry {
+    pub credential: String,
+    pub expires: SystemTime,
+    // What to do about it
+    pub renew_hint: String,
+}
+
+// A gauge of a provider in /metrics, e.g. the calls left of a rate limited API
+#[derive(Clone)]
+pub struct ProviderMetric {
+    // Without the get_rich_slow_ prefix, e.g. nordigen_quota_remaining
+    pub name: String,
+    pub help: String,
+    pub labels: Vec<(String, String)>,
+    pub value: f64,
+}
+
+// Everything which holds assets (exchange, bank, broker, wallet)
+// implements this trait. New integrations only have to implement it
+// and register themselves in ProviderRegistry::default.
+#[rocket::async_trait]
+pub trait AccountProvider: Send + Sync {
+    // Build and validate the account from its config entry.
+    fn from_config(name: &String, config: &AccountConfig) -> Result<Self, String>
+    where
+        Self: Sized;
+
+    // Optional: set up what all accounts of the provider share, like its caches.
+    // Called once before the first account is used.
+    fn configure(_config: &Configuration)
+    where
+        Self: Sized,
+    {
+    }
+
+    // Optional: lookups of the caches set up in configure
+    fn get_cache_stats() -> Vec<(String, CacheStats)>
+    where
+        Self: Sized,
+    {
+        Vec::new()
+    }
+
+    // Optional: gauges of what all accounts of the provider share, like a quota
+    fn get_metrics() -> Vec<ProviderMetric>
+    where
+        Self: Sized,
+    {
+        Vec::new()
+    }
+
+    fn get_name(&self) -> String;
+    fn get_kind(&self) -> String;
+    async fn get_assets(
+        &self,
+        domainconfig: &DomainConfig,
+    ) -> Result<Vec<Box<dyn Asset>>, ApiError>;
+
+    // Optional: all balances the provider reports, e.g. booked and available
+    async fn get_balances(
+        &self,
+        _domainconfig: &DomainConfig,
+    ) -> Result<Vec<BalanceDto>, ApiError> {
+        Err(ApiError::new(&format!(
+            "Account {} ({}) has no balance details",
+            self.get_name(),
+            self.get_kind()
+        )))
+    }
+
+    // Optional: expiry of credentials which need the user to renew them
+    async fn get_credential_expiry(
+        &self,
+        _domainconfig: &DomainConfig,
+    ) -> Result<Vec<CredentialExpiry>, ApiError> {
+        Ok(Vec::new())
+    }
+
+    // Optional: cash flows, dates as YYYY-MM-DD
+    async fn get_transactions(
+        &self,
+        _domainconfig: &DomainConfig,
+        _from: Option<&String>,
+        _to: Option<&String>,
+    ) -> Result<Vec<TransactionDto>, ApiError> {
+        Err(ApiError::new(&format!(
+            "Account {} ({}) has no transactions",
+            self.get_name(),
+            self.get_kind()
+        )))
+    }
+}
+
+pub type ProviderFactory = fn(&String, &AccountConfig) -> Result<Arc<dyn AccountProvider>, String>;
+
+fn build<T: AccountProvider + 'static>(
+    name: &String,
+    config: &AccountConfig,
+) -> Result<Arc<dyn AccountProvider>, String> {
+    Ok(Arc::new(T::from_config(name, config)?))
+}
+
+// Hooks of a provider, once per provider even if it serves several kinds
+struct ProviderHooks {
+    configure: fn(&Configuration),
+    get_cache_stats: fn() -> Vec<(String, CacheStats)>,
+    get_metrics: fn() -> Vec<ProviderMetric>,
+}
+
+pub struct ProviderRegistry {
+    factories: HashMap<String, ProviderFactory>,
+    hooks: Vec<ProviderHooks>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        Self {
+            factories: HashMap::new(),
+            hooks: Vec::new(),
+        }
+    }
+
+    pub fn register<T: AccountProvider + 'static>(&mut self, kinds: &[&str]) {
+        for kind in kinds.iter() {
+            self.factories.insert(kind.to_string(), build::<T>);
+        }
+        self.hooks.push(ProviderHooks {
+            configure: T::configure,
+            get_cache_stats: T::get_cache_stats,
+            get_metrics: T::get_metrics,
+        });
+    }
+
+    pub fn configure(&self, config: &Configuration) {
+        for hooks in self.hooks.iter() {
+            (hooks.configure)(config);
+        }
+    }
+
+    pub fn get_cache_stats(&self) -> Vec<(String, CacheStats)> {
+        self.hooks
+            .iter()
+            .flat_map(|hooks| (hooks.get_cache_stats)())
+            .collect()
+    }
+
+    pub fn get_metrics(&self) -> Vec<ProviderMetric> {
+        self.hooks
+            .iter()
+            .flat_map(|hooks| (hooks.get_metrics)())
+            .collect()
+    }
+
+    pub fn create(
+        &self,
+        name: &String,
+        config: &AccountConfig,
+    ) -> Result<Arc<dyn AccountProvider>, String> {
+        match self.factories.get(&config.kind) {
+            None => Err(format!(
+                "Invalid account type {} of account {}",
+                config.kind, name
+            )),
+            Some(factory) => factory(name, config),
+        }
+    }
+}
+
+impl Default for ProviderRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register::<KrakenAccount>(&["kraken"]);
+        registry.register::<NordigenAccount>(&["nordigen"]);
+        registry.register::<ScalableAccount>(&["scalable"]);
+        registry.register::<EthereumAccount>(&EthereumChain::get_identifiers());
+        registry
+    }
+}
+
+pub fn require(
+    value: &Option<String>,
+    field: &str,
+    name: &String,
+    kind: &str,
+) -> Result<String, String> {
+    match value {
+        Some(v) if !v.is_empty() => Ok(v.clone()),
+        _ => Err(format!(
+            "Account {} of type {} requires {}",
+            name, kind, field
+        )),
+    }
+}
+
+pub fn boxed<T: Asset + 'static>(assets: Vec<T>) -> Vec<Box<dyn Asset>> {
+    assets
+        .into_iter()
+        .map(|a| Box::new(a) as Box<dyn Asset>)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn account_config(kind: &str) -> AccountConfig {
+        AccountConfig {
+            kind: kind.to_string(),
+            api_key: None,
+            api_secret: None,
+            address: None,
+            refresh_token: None,
+            account_id: None,
+            email: None,
+            password: None,
+            totp_secret: None,
+            portfolio_id: None,
+        }
+    }
+
+    #[test]
+    fn test_registry_validation() {
+        let registry = ProviderRegistry::default();
+        let name = "test".to_string();
+        assert!(registry.create(&name, &account_config("etoro")).is_err());
+        assert!(registry.create(&name, &account_config("kraken")).is_err());
+
+        let mut config = account_config("bsc");
+        config.address = Some("0x7c9e73d4c71dae564d41f78d56439bb4ba87592f".to_string());
+        let account = registry.create(&name, &config).unwrap();
+        assert_eq!(account.get_kind(), "bsc");
+        assert_eq!(account.get_name(), "test");
+
+        // Every chain is an account kind
+        for chain in EthereumChain::ALL.iter() {
+            config.kind = chain.to_identifier().to_string();
+            assert!(registry.create(&name, &config).is_ok());
+        }
+    }
+}
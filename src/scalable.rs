@@ -1,136 +1,316 @@
 use super::account::ScalableAccount;
-use super::asset::GenericAsset;
-use super::cryptoprice::get_token_price;
+use super::asset::{Asset, GenericAsset};
+use super::cache::{default_cache_settings, CacheLookup, CacheSettings, CacheStats, TtlCache};
+use super::config::{Account as AccountConfig, Configuration};
+use super::currency::FiatCurrency;
+use super::domainconfig::DomainConfig;
 use super::error::ApiError;
+use super::fx::get_fx_rate;
+use super::jwt::Jwt;
+use super::provider::{boxed, require, AccountProvider};
+use super::scalablegraphql::{
+    build_request, parse_response, BrokerPortfolio, BrokerPortfolioInventory, BrokerPortfolios,
+    Operation, OwnedItem, PersonVariables, PortfolioVariables, Response,
+};
+use super::totp;
 use reqwest::Client;
-use serde_json::{from_str, Value};
+use serde_json::Value;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
 
 static LOGIN_URL: &str = "https://de.scalable.capital/actions/login";
+static MFA_URL: &str = "https://de.scalable.capital/actions/login/mfa";
 static PORTFOLIO_URL: &str = "https://de.scalable.capital/cockpit/graphql";
+// Log in again this long before the access token expires
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(300);
+// For access tokens without an expiry
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(3600);
 
 pub struct ScalableCache {
-    access_token_map: Arc<Mutex<HashMap<String, String>>>,
+    // Access tokens per email
+    access_tokens: TtlCache<String, ScalableToken>,
+    // Assets per account name
+    assets: TtlCache<String, Vec<GenericAsset>>,
+    // Login challenges waiting for the confirmation in the app, per email
+    challenges: Mutex<HashMap<String, String>>,
 }
 
 impl ScalableCache {
-    pub fn new() -> Self {
+    pub fn new(asset_settings: CacheSettings) -> Self {
         Self {
-            access_token_map: Arc::new(Mutex::new(HashMap::new())),
+            // Each token is cached until shortly before it expires
+            access_tokens: TtlCache::new(CacheSettings::new(
+                DEFAULT_TOKEN_LIFETIME,
+                Duration::from_secs(0),
+            )),
+            assets: TtlCache::new(asset_settings),
+            challenges: Mutex::new(HashMap::new()),
         }
     }
 
-    pub fn get(&self, email: &String) -> Option<String> {
-        match self.access_token_map.lock().unwrap().get(email) {
-            None => None,
-            Some(v) => Some(v.clone()),
-        }
+    pub fn get_stats(&self) -> Vec<(String, CacheStats)> {
+        vec![
+            (
+                "scalable-tokens".to_string(),
+                self.access_tokens.get_stats(),
+            ),
+            ("scalable-assets".to_string(), self.assets.get_stats()),
+        ]
     }
+}
 
-    pub fn set(&self, email: String, s: String) {
-        self.access_token_map.lock().unwrap().insert(email, s);
-    }
+static SCALABLE_CACHE: OnceLock<ScalableCache> = OnceLock::new();
+
+fn scalable_cache() -> &'static ScalableCache {
+    SCALABLE_CACHE.get_or_init(|| ScalableCache::new(default_cache_settings()))
 }
 
-fn get_graphql_request(person_id: String) -> Value {
-    // TODO use GraphQL
-    let data = r#"
-    [
-        {
-        "operationName": "getBrokerPortfolios",
-        "variables": {
-            "personId": "PERSON_ID"
-        },
-        "query": "query getBrokerPortfolios($personId: ID!) {\n  account(id: $personId) {\n    id\n    ...BrokerPortfoliosOnAccountFragment\n    __typename\n  }\n}\n\nfragment BrokerPortfoliosOnAccountFragment on Account {\n  brokerPortfolios {\n    id\n    totalSavingsPlanAmount\n    numberOfPendingOrders\n    postOnboardingInfo {\n      id\n      allStepsCompleted\n      steps {\n        status\n        type\n        __typename\n      }\n      __typename\n    }\n    valuation {\n      id\n      valuation\n      cryptoValuation\n      time\n      timeWeightedReturnByTimeframe {\n        timeframe\n        performance\n        simpleAbsoluteReturn\n        __typename\n      }\n      __typename\n    }\n    selectedOffer {\n      id\n      __typename\n    }\n    __typename\n  }\n  __typename\n}\n"
-        }
-    ]
-    "#;
-    serde_json::from_str(&data.replace("PERSON_ID", &person_id)).unwrap()
+// A single security or crypto currency held in the portfolio
+#[derive(Debug, PartialEq)]
+struct ScalablePosition {
+    isin: String,
+    name: String,
+    quantity: f32,
+    price: f32,
+    currency: FiatCurrency,
+    // Quoted in a currency without an exchange rate, the price is zero
+    unpriced: bool,
 }
 
-fn stock_crypto_balance_from_json(v: Value) -> Result<(f32, f32), ApiError> {
-    let default_error = ApiError::new(&String::from("Unexpected Scalable GraphQL response format"));
+// Timeframe of the return used as yearly growth
+const YEARLY_TIMEFRAME: &str = "one_year";
 
-    let first = &v[0];
-    let data = first.get("data");
-    if data.is_none() {
-        return Err(default_error);
-    }
-    let account = data.unwrap().get("account");
-    if account.is_none() {
-        return Err(default_error);
+struct ScalablePortfolio {
+    id: String,
+    // Time-weighted return per timeframe
+    returns: Vec<(String, f32)>,
+}
+
+fn portfolio_from_response(portfolio: &BrokerPortfolio) -> ScalablePortfolio {
+    ScalablePortfolio {
+        id: portfolio.id.clone(),
+        returns: portfolio
+            .valuation
+            .time_weighted_return_by_timeframe
+            .iter()
+            .filter_map(|r| {
+                r.performance
+                    .map(|p| (r.timeframe.to_lowercase(), p as f32))
+            })
+            .collect(),
     }
-    let broker = account.unwrap().get("brokerPortfolios");
-    if broker.is_none() {
-        return Err(default_error);
+}
+
+// The configured portfolio, or all of them
+fn select_portfolios(
+    portfolios: Vec<ScalablePortfolio>,
+    portfolio_id: &Option<String>,
+) -> Result<Vec<ScalablePortfolio>, ApiError> {
+    let id = match portfolio_id {
+        None => return Ok(portfolios),
+        Some(id) => id,
+    };
+    let available: Vec<String> = portfolios.iter().map(|p| p.id.clone()).collect();
+    let selected: Vec<ScalablePortfolio> = portfolios.into_iter().filter(|p| &p.id == id).collect();
+    if selected.is_empty() {
+        return Err(ApiError::new(&format!(
+            "Scalable portfolio {} not found, available: {}",
+            id,
+            available.join(", ")
+        )));
     }
-    let portfolio = &broker.unwrap()[0];
-    let valuation = portfolio.get("valuation");
-    if valuation.is_none() {
-        return Err(default_error);
+    Ok(selected)
+}
+
+// None for positions sold entirely
+fn position_from_item(item: &OwnedItem) -> Result<Option<ScalablePosition>, ApiError> {
+    let quantity = item.quantity as f32;
+    if quantity == 0. {
+        return Ok(None);
     }
-    let valuation = valuation.unwrap();
+    let quote = item
+        .quote_tick
+        .as_ref()
+        .and_then(|q| q.mid_price.map(|p| (p, q.currency.as_ref())));
+    let mut unknown_currency = false;
+    let quote = match quote {
+        Some((price, Some(code))) => match FiatCurrency::from_code(code) {
+            Ok(currency) => Some((price, currency)),
+            Err(e) => {
+                unknown_currency = true;
+                println!(
+                    "Ignoring quote of Scalable position {}: {}",
+                    item.isin,
+                    e.get_message()
+                );
+                None
+            }
+        },
+        Some((price, None)) => Some((price, FiatCurrency::EUR)),
+        None => None,
+    };
+    // Without a quote the price follows from the value of the position in EUR
+    let (price, currency, unpriced) = match (quote, item.fiat_amount) {
+        (Some((price, currency)), _) => (price as f32, currency, false),
+        (None, Some(amount)) => (amount as f32 / quantity, FiatCurrency::EUR, false),
+        (None, None) if unknown_currency => (0.0, FiatCurrency::EUR, true),
+        (None, None) => {
+            return Err(ApiError::new(&format!(
+                "Scalable position {} has neither a quote nor a value",
+                item.isin
+            )))
+        }
+    };
+    Ok(Some(ScalablePosition {
+        isin: item.isin.clone(),
+        name: item.name.clone().unwrap_or(item.isin.clone()),
+        quantity: quantity,
+        price: price,
+        currency: currency,
+        unpriced: unpriced,
+    }))
+}
 
-    let stock_valuation = valuation.get("valuation");
-    let crypto_valuation = valuation.get("cryptoValuation");
+#[derive(Clone)]
+struct ScalableToken {
+    access_token: String,
+    person_id: String,
+}
 
-    if stock_valuation.is_none() {
-        return Err(default_error);
-    }
-    if crypto_valuation.is_none() {
-        return Err(default_error);
-    }
+// Returns the token and how long it can be used before logging in again
+fn parse_access_token(access_token: String) -> Result<(ScalableToken, Duration), ApiError> {
+    let jwt = Jwt::parse(&access_token)?;
+    let person_id = match jwt.get_claim("person_id").and_then(|p| p.as_str()) {
+        Some(p) => p.to_string(),
+        None => {
+            return Err(ApiError::new(
+                &"person_id not found in Scalable access token".to_string(),
+            ))
+        }
+    };
+    let lifetime = match jwt.get_expiry() {
+        Some(expiry) => expiry
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+            .saturating_sub(TOKEN_REFRESH_MARGIN),
+        None => DEFAULT_TOKEN_LIFETIME,
+    };
     Ok((
-        stock_valuation.unwrap().as_f64().unwrap() as f32,
-        crypto_valuation.unwrap().as_f64().unwrap() as f32,
+        ScalableToken {
+            access_token: access_token,
+            person_id: person_id,
+        },
+        lifetime,
     ))
 }
 
-fn get_person_id_from_access_token(access_token: String) -> Result<String, ApiError> {
-    // Scalable uses a special format, not supported by the jwt lib.
-    // Therefore we have to get our information manually.
+enum LoginStep {
+    AccessToken(String),
+    // A second factor is needed to finish the login
+    Challenge(String),
+}
 
-    // header.payload.signature
+fn parse_login_response(result: &Value) -> Result<LoginStep, ApiError> {
+    let v = result.get(0).unwrap_or(result);
+    if let Some(token) = v.get("accessToken").and_then(|t| t.as_str()) {
+        return Ok(LoginStep::AccessToken(token.to_string()));
+    }
+    if let Some(challenge) = v.get("challengeId").and_then(|c| c.as_str()) {
+        return Ok(LoginStep::Challenge(challenge.to_string()));
+    }
+    Err(ApiError::new(&format!(
+        "Scalable Capital login failed: {}",
+        v.get("message")
+            .and_then(|m| m.as_str())
+            .unwrap_or("no access token")
+    )))
+}
 
-    let mut split = access_token.split(".");
-    split.next(); // header
-    let payload = split.next().unwrap().clone();
-    let bytes = base64::decode(&payload.to_string()).unwrap();
-    let json_text = std::str::from_utf8(&bytes).unwrap();
+async fn post_login(url: &str, map: &HashMap<String, String>) -> Result<LoginStep, ApiError> {
+    let result = Client::new()
+        .post(url)
+        .json(map)
+        .send()
+        .await?
+        .json::<Value>()
+        .await?;
+    parse_login_response(&result)
+}
 
-    let value: Value = from_str(json_text).unwrap();
+fn confirmation_pending(account: &ScalableAccount) -> ApiError {
+    ApiError::pending(&format!(
+        "Scalable Capital login of account {} is waiting to be confirmed in the app, \
+        or configure totp-secret",
+        account.name
+    ))
+}
 
-    match value.get("person_id") {
-        None => Err(ApiError::new(&String::from(
-            "person_id not found in Scalabe JWT",
-        ))),
-        Some(v) => Ok(v.as_str().unwrap().to_string()),
+// With a TOTP secret the code is sent right away. Otherwise the login has to be
+// confirmed in the app, the challenge is kept and checked again on the next refresh.
+async fn get_access_token(
+    cache: &ScalableCache,
+    account: &ScalableAccount,
+) -> Result<String, ApiError> {
+    let pending = cache
+        .challenges
+        .lock()
+        .unwrap()
+        .get(&account.email)
+        .cloned();
+    if let Some(challenge) = pending {
+        let mut map = HashMap::new();
+        map.insert("challengeId".to_string(), challenge);
+        match post_login(MFA_URL, &map).await {
+            Ok(LoginStep::AccessToken(token)) => {
+                cache.challenges.lock().unwrap().remove(&account.email);
+                return Ok(token);
+            }
+            Ok(LoginStep::Challenge(_)) => return Err(confirmation_pending(account)),
+            // E.g. the challenge expired, log in again
+            Err(e) => {
+                println!(
+                    "Pending Scalable login of account {} failed: {}",
+                    account.name,
+                    e.get_message()
+                );
+                cache.challenges.lock().unwrap().remove(&account.email);
+            }
+        }
     }
-}
 
-async fn get_access_token(email: &String, password: &String) -> Result<String, ApiError> {
     let mut map = HashMap::new();
-    map.insert("username".to_string(), email.clone());
-    map.insert("password".to_string(), password.clone());
+    map.insert("username".to_string(), account.email.clone());
+    map.insert("password".to_string(), account.password.clone());
 
     println!("Perform Scalable login");
-    let client = Client::new();
-    let result = client
-        .post(LOGIN_URL)
-        .json(&map)
-        .send()
-        .await?
-        .json::<Value>()
-        .await?;
+    let challenge = match post_login(LOGIN_URL, &map).await? {
+        LoginStep::AccessToken(token) => return Ok(token),
+        LoginStep::Challenge(challenge) => challenge,
+    };
 
-    match result.get(0) {
-        None => Err(ApiError::new(&format!("Scalable Capital login failed."))),
-        Some(v) => match v.get("accessToken") {
-            None => Err(ApiError::new(&format!("Scalable Capital login failed."))),
-            Some(x) => Ok(x.as_str().unwrap().to_string()),
-        },
+    if let Some(secret) = &account.totp_secret {
+        let mut map = HashMap::new();
+        map.insert("challengeId".to_string(), challenge);
+        map.insert("code".to_string(), totp::get_code(secret)?);
+        return match post_login(MFA_URL, &map).await? {
+            LoginStep::AccessToken(token) => Ok(token),
+            LoginStep::Challenge(_) => Err(ApiError::new(
+                &"Scalable Capital rejected the TOTP code, check totp-secret".to_string(),
+            )),
+        };
     }
+
+    println!(
+        "Confirm the Scalable Capital login of account {} in the app",
+        account.name
+    );
+    cache
+        .challenges
+        .lock()
+        .unwrap()
+        .insert(account.email.clone(), challenge);
+    Err(confirmation_pending(account))
 }
 
 enum AssetResult {
@@ -138,68 +318,281 @@ enum AssetResult {
     Assets(Vec<GenericAsset>),
 }
 
-async fn get_assets(access_token: &String, account_name: &String) -> Result<AssetResult, ApiError> {
-    let person_id = get_person_id_from_access_token(access_token.clone())?;
+// None if the access token was rejected
+async fn post_graphql<O: Operation>(
+    token: &ScalableToken,
+    variables: O::Variables,
+) -> Result<Option<O::Data>, ApiError> {
     let client = Client::new();
     let result = client
         .post(PORTFOLIO_URL)
-        .header("Authorization", format!("Bearer {}", access_token))
-        .json(&get_graphql_request(person_id))
+        .header("Authorization", format!("Bearer {}", token.access_token))
+        .json(&build_request::<O>(variables))
         .send()
         .await?
         .json::<Value>()
         .await?;
 
-    if result[0].get("errors").is_some() {
-        return Ok(AssetResult::AccessExpired);
-    }
-    let (stocks, crypto) = stock_crypto_balance_from_json(result)?;
-
-    let eur_price = get_token_price(&"EUR".to_string()).await?;
-    Ok(AssetResult::Assets(vec![
-        GenericAsset::new(
-            0.0,
-            "Stocks on Scalable".to_string(),
-            format!("{} | Stocks", account_name),
-            stocks,
-            eur_price,
-        ),
-        GenericAsset::new(
-            0.0,
-            "Crypto on Scalable".to_string(),
-            format!("{} | Crypto", account_name),
-            crypto,
-            eur_price,
-        ),
-    ]))
+    match parse_response::<O>(&result)? {
+        Response::Data(data) => Ok(Some(data)),
+        Response::Unauthenticated => {
+            println!("Scalable {} was not authenticated", O::NAME);
+            Ok(None)
+        }
+    }
 }
 
-pub async fn get_assets_of_scalable_account(
+// One asset per position, named like the security
+async fn get_assets(
+    token: &ScalableToken,
+    account: &ScalableAccount,
+) -> Result<AssetResult, ApiError> {
+    let variables = PersonVariables {
+        person_id: token.person_id.clone(),
+    };
+    let portfolios = match post_graphql::<BrokerPortfolios>(token, variables).await? {
+        Some(data) => data
+            .account
+            .broker_portfolios
+            .iter()
+            .map(portfolio_from_response)
+            .collect(),
+        None => return Ok(AssetResult::AccessExpired),
+    };
+    let portfolios = select_portfolios(portfolios, &account.portfolio_id)?;
+
+    let mut assets = Vec::new();
+    for portfolio in portfolios.iter() {
+        let variables = PortfolioVariables {
+            person_id: token.person_id.clone(),
+            portfolio_id: portfolio.id.clone(),
+        };
+        let items = match post_graphql::<BrokerPortfolioInventory>(token, variables).await? {
+            Some(data) => data.account.broker_portfolio.inventory.owned_items,
+            None => return Ok(AssetResult::AccessExpired),
+        };
+
+        // Scalable reports returns only for the whole portfolio, every position gets them
+        let yearly = portfolio
+            .returns
+            .iter()
+            .find(|(timeframe, _)| timeframe == YEARLY_TIMEFRAME)
+            .map(|(_, performance)| *performance)
+            .unwrap_or(0.0);
+        let description = if portfolios.len() > 1 {
+            format!("{} | {}", account.name, portfolio.id)
+        } else {
+            account.name.clone()
+        };
+        for item in items.iter() {
+            let position = match position_from_item(item)? {
+                Some(p) => p,
+                None => continue,
+            };
+            let rate = get_fx_rate(&position.currency, &FiatCurrency::USD).await?;
+            let asset = GenericAsset::new(
+                yearly,
+                position.name,
+                format!("{} | {}", description, position.isin),
+                position.quantity,
+                position.price * rate,
+            )
+            .with_returns(portfolio.returns.clone());
+            assets.push(if position.unpriced {
+                asset.as_unpriced()
+            } else {
+                asset
+            });
+        }
+    }
+    Ok(AssetResult::Assets(assets))
+}
+
+async fn login(
+    cache: &ScalableCache,
+    account: &ScalableAccount,
+) -> Result<ScalableToken, ApiError> {
+    let (token, lifetime) = parse_access_token(get_access_token(cache, account).await?)?;
+    cache.access_tokens.insert_with(
+        account.email.clone(),
+        token.clone(),
+        CacheSettings::new(lifetime, Duration::from_secs(0)),
+    );
+    Ok(token)
+}
+
+async fn fetch_assets(
     cache: &ScalableCache,
     account: &ScalableAccount,
 ) -> Result<Vec<GenericAsset>, ApiError> {
-    let cached_access_token = cache.get(&account.email);
-    let access_token = match cached_access_token {
-        None => {
-            let access = get_access_token(&account.email, &account.password).await?;
-            cache.set(account.email.clone(), access.clone());
-            access
-        }
-        Some(v) => v,
+    let token = match cache.access_tokens.get(&account.email) {
+        CacheLookup::Fresh(token) => token,
+        _ => login(cache, account).await?,
     };
 
-    match get_assets(&access_token, &account.name).await? {
+    match get_assets(&token, account).await? {
         AssetResult::AccessExpired => (),
         AssetResult::Assets(a) => return Ok(a),
     }
 
-    let access_token = get_access_token(&account.email, &account.password).await?;
-    cache.set(account.email.clone(), access_token.clone());
-
-    match get_assets(&access_token, &account.name).await? {
+    let token = login(cache, account).await?;
+    match get_assets(&token, account).await? {
         AssetResult::AccessExpired => Err(ApiError::new(
             &"Scalable access expired immediately".to_string(),
         )),
         AssetResult::Assets(a) => Ok(a),
     }
 }
+
+pub async fn get_assets_of_scalable_account(
+    cache: &ScalableCache,
+    account: &ScalableAccount,
+) -> Result<Vec<GenericAsset>, ApiError> {
+    cache
+        .assets
+        .get_or_fetch(account.name.clone(), || fetch_assets(cache, account))
+        .await
+}
+
+#[rocket::async_trait]
+impl AccountProvider for ScalableAccount {
+    fn from_config(name: &String, config: &AccountConfig) -> Result<Self, String> {
+        Ok(Self {
+            name: name.clone(),
+            email: require(&config.email, "email", name, "scalable")?,
+            password: require(&config.password, "password", name, "scalable")?,
+            totp_secret: config.totp_secret.clone(),
+            portfolio_id: config.portfolio_id.clone(),
+        })
+    }
+
+    fn configure(config: &Configuration) {
+        let _ = SCALABLE_CACHE.set(ScalableCache::new(
+            config.get_cache_settings("scalable", default_cache_settings()),
+        ));
+    }
+
+    fn get_cache_stats() -> Vec<(String, CacheStats)> {
+        scalable_cache().get_stats()
+    }
+
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_kind(&self) -> String {
+        "scalable".to_string()
+    }
+
+    async fn get_assets(
+        &self,
+        _domainconfig: &DomainConfig,
+    ) -> Result<Vec<Box<dyn Asset>>, ApiError> {
+        Ok(boxed(
+            get_assets_of_scalable_account(scalable_cache(), self).await?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_positions_and_portfolios() {
+        let items: Vec<OwnedItem> = serde_json::from_value(json!([
+            {"isin": "IE00B4L5Y983", "name": "iShares Core MSCI World", "quantity": 10.5,
+             "fiatAmount": 787.5, "quoteTick": {"midPrice": 75.0, "currency": "EUR"}},
+            {"isin": "US0378331005", "name": "Apple", "quantity": 2,
+             "fiatAmount": 300.0, "quoteTick": {"midPrice": null, "currency": "USD"}},
+            {"isin": "DE0005140008", "quantity": 0},
+            {"isin": "XF000BTC0017", "quantity": 1},
+            {"isin": "GB0002374006", "quantity": 4,
+             "fiatAmount": 100.0, "quoteTick": {"midPrice": 2000.0, "currency": "GBX"}},
+            {"isin": "JP3633400001", "quantity": 3,
+             "quoteTick": {"midPrice": 2500.0, "currency": "XYZ"}}
+        ]))
+        .unwrap();
+        assert_eq!(
+            position_from_item(&items[0]).unwrap(),
+            Some(ScalablePosition {
+                isin: "IE00B4L5Y983".to_string(),
+                name: "iShares Core MSCI World".to_string(),
+                quantity: 10.5,
+                price: 75.0,
+                currency: FiatCurrency::EUR,
+                unpriced: false,
+            })
+        );
+        let apple = position_from_item(&items[1]).unwrap().unwrap();
+        assert_eq!((apple.price, apple.currency), (150.0, FiatCurrency::EUR));
+        assert_eq!(position_from_item(&items[2]).unwrap(), None);
+        assert!(position_from_item(&items[3]).is_err());
+        // An unknown currency falls back to the value in EUR, or leaves the position unpriced
+        let diageo = position_from_item(&items[4]).unwrap().unwrap();
+        assert_eq!((diageo.price, diageo.currency), (25.0, FiatCurrency::EUR));
+        let unpriced = position_from_item(&items[5]).unwrap().unwrap();
+        assert!(unpriced.unpriced);
+        assert_eq!(unpriced.quantity, 3.0);
+
+        let portfolio: BrokerPortfolio = serde_json::from_value(json!({"id": "p1", "valuation": {
+            "timeWeightedReturnByTimeframe": [
+                {"timeframe": "ONE_MONTH", "performance": 0.02, "simpleAbsoluteReturn": 40.0},
+                {"timeframe": "ONE_YEAR", "performance": 0.12, "simpleAbsoluteReturn": 220.0},
+                {"timeframe": "MAX", "performance": null}
+            ]
+        }}))
+        .unwrap();
+        let portfolio = portfolio_from_response(&portfolio);
+        assert_eq!(
+            portfolio.returns,
+            vec![
+                ("one_month".to_string(), 0.02),
+                ("one_year".to_string(), 0.12)
+            ]
+        );
+
+        let portfolios = vec![
+            portfolio,
+            ScalablePortfolio {
+                id: "p2".to_string(),
+                returns: Vec::new(),
+            },
+        ];
+        let selected = select_portfolios(portfolios, &Some("p2".to_string())).unwrap();
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].id, "p2");
+        assert!(select_portfolios(selected, &Some("p3".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_parse_login_response() {
+        assert!(matches!(
+            parse_login_response(&json!([{"accessToken": "a.b.c"}])).unwrap(),
+            LoginStep::AccessToken(t) if t == "a.b.c"
+        ));
+        assert!(matches!(
+            parse_login_response(&json!([{"challengeId": "c1", "type": "TOTP"}])).unwrap(),
+            LoginStep::Challenge(c) if c == "c1"
+        ));
+        assert!(parse_login_response(&json!([{"message": "Invalid credentials"}])).is_err());
+    }
+
+    #[test]
+    fn test_parse_access_token() {
+        let token = |claims: &str| format!("e30.{}.signature", base64::encode(claims));
+        let (parsed, lifetime) =
+            parse_access_token(token(r#"{"person_id": "p1", "exp": 4102444800}"#)).unwrap();
+        assert_eq!(parsed.person_id, "p1");
+        assert!(lifetime > Duration::from_secs(3600));
+
+        // Expired tokens are used only once
+        let (_, lifetime) =
+            parse_access_token(token(r#"{"person_id": "p1", "exp": 1000}"#)).unwrap();
+        assert_eq!(lifetime, Duration::from_secs(0));
+
+        assert!(parse_access_token(token(r#"{"sub": "p1"}"#)).is_err());
+        assert!(parse_access_token("no token".to_string()).is_err());
+    }
+}
@@ -0,0 +1,315 @@
+use super::error::ApiError;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Operations of the Scalable Capital cockpit GraphQL API. The API is not
+// public, the response types hold only the fields read by get-rich-slow.
+// Responses of each query version are kept in fixtures/scalable/<version>.
+
+pub trait Operation {
+    const NAME: &'static str;
+    const QUERY: &'static str;
+    type Variables: Serialize;
+    type Data: DeserializeOwned;
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Request<V> {
+    operation_name: &'static str,
+    variables: V,
+    query: &'static str,
+}
+
+// The cockpit sends batches, a single request is a batch of one
+pub fn build_request<O: Operation>(variables: O::Variables) -> Vec<Request<O::Variables>> {
+    vec![Request {
+        operation_name: O::NAME,
+        variables: variables,
+        query: O::QUERY,
+    }]
+}
+
+pub enum Response<D> {
+    Data(D),
+    // The access token expired or was rejected
+    Unauthenticated,
+}
+
+fn is_unauthenticated(error: &Value) -> bool {
+    error.pointer("/extensions/code").and_then(|c| c.as_str()) == Some("UNAUTHENTICATED")
+        || error.get("message").and_then(|m| m.as_str()) == Some("Unauthorized")
+}
+
+pub fn parse_response<O: Operation>(result: &Value) -> Result<Response<O::Data>, ApiError> {
+    let first = match result.as_array() {
+        Some(batch) => batch.first().unwrap_or(&Value::Null),
+        None => result,
+    };
+    if let Some(errors) = first.get("errors").and_then(|e| e.as_array()) {
+        if errors.iter().any(is_unauthenticated) {
+            return Ok(Response::Unauthenticated);
+        }
+        let messages: Vec<String> = errors
+            .iter()
+            .map(|e| {
+                e.get("message")
+                    .and_then(|m| m.as_str())
+                    .map(|m| m.to_string())
+                    .unwrap_or(e.to_string())
+            })
+            .collect();
+        return Err(ApiError::new(&format!(
+            "Scalable {} failed: {}",
+            O::NAME,
+            messages.join(", ")
+        )));
+    }
+    let data = first.get("data").unwrap_or(&Value::Null);
+    // The path tells which field Scalable changed, e.g. account.brokerPortfolios[0].id
+    match serde_path_to_error::deserialize(data) {
+        Ok(data) => Ok(Response::Data(data)),
+        Err(e) => Err(ApiError::new(&format!(
+            "Unexpected Scalable {} response at data.{}: {}",
+            O::NAME,
+            e.path(),
+            e.inner()
+        ))),
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PersonVariables {
+    pub person_id: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PortfolioVariables {
+    pub person_id: String,
+    pub portfolio_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct AccountData<A> {
+    pub account: A,
+}
+
+pub struct BrokerPortfolios;
+
+impl Operation for BrokerPortfolios {
+    const NAME: &'static str = "getBrokerPortfolios";
+    const QUERY: &'static str = "query getBrokerPortfolios($personId: ID!) {
+  account(id: $personId) {
+    id
+    brokerPortfolios {
+      id
+      valuation {
+        id
+        valuation
+        cryptoValuation
+        time
+        timeWeightedReturnByTimeframe {
+          timeframe
+          performance
+          simpleAbsoluteReturn
+          __typename
+        }
+        __typename
+      }
+      __typename
+    }
+    __typename
+  }
+}
+";
+    type Variables = PersonVariables;
+    type Data = AccountData<PortfoliosAccount>;
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PortfoliosAccount {
+    pub broker_portfolios: Vec<BrokerPortfolio>,
+}
+
+#[derive(Deserialize)]
+pub struct BrokerPortfolio {
+    pub id: String,
+    pub valuation: Valuation,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Valuation {
+    pub time_weighted_return_by_timeframe: Vec<TimeWeightedReturn>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeWeightedReturn {
+    pub timeframe: String,
+    // 10% are 0.1, missing for timeframes older than the portfolio
+    pub performance: Option<f64>,
+}
+
+pub struct BrokerPortfolioInventory;
+
+impl Operation for BrokerPortfolioInventory {
+    const NAME: &'static str = "getBrokerPortfolioInventory";
+    const QUERY: &'static str =
+        "query getBrokerPortfolioInventory($personId: ID!, $portfolioId: ID!) {
+  account(id: $personId) {
+    id
+    brokerPortfolio(id: $portfolioId) {
+      id
+      inventory {
+        ownedItems {
+          isin
+          name
+          quantity
+          fiatAmount
+          quoteTick {
+            midPrice
+            currency
+            time
+            __typename
+          }
+          __typename
+        }
+        __typename
+      }
+      __typename
+    }
+    __typename
+  }
+}
+";
+    type Variables = PortfolioVariables;
+    type Data = AccountData<InventoryAccount>;
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InventoryAccount {
+    pub broker_portfolio: InventoryPortfolio,
+}
+
+#[derive(Deserialize)]
+pub struct InventoryPortfolio {
+    pub inventory: Inventory,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Inventory {
+    pub owned_items: Vec<OwnedItem>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnedItem {
+    pub isin: String,
+    pub name: Option<String>,
+    pub quantity: f64,
+    // Value of the position in EUR
+    pub fiat_amount: Option<f64>,
+    pub quote_tick: Option<QuoteTick>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuoteTick {
+    pub mid_price: Option<f64>,
+    pub currency: Option<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse_fixture<O: Operation>(fixture: &str) -> Result<Response<O::Data>, ApiError> {
+        parse_response::<O>(&serde_json::from_str(fixture).unwrap())
+    }
+
+    fn data<D>(response: Response<D>) -> D {
+        match response {
+            Response::Data(d) => d,
+            Response::Unauthenticated => panic!("Unexpected unauthenticated response"),
+        }
+    }
+
+    #[test]
+    fn test_build_request() {
+        let request = build_request::<BrokerPortfolios>(PersonVariables {
+            person_id: "p1".to_string(),
+        });
+        let request = serde_json::to_value(&request).unwrap();
+        assert_eq!(request[0]["operationName"], "getBrokerPortfolios");
+        assert_eq!(request[0]["variables"]["personId"], "p1");
+        assert!(request[0]["query"]
+            .as_str()
+            .unwrap()
+            .starts_with("query getBrokerPortfolios($personId: ID!)"));
+    }
+
+    #[test]
+    fn test_v1_fixtures() {
+        let portfolios = data(
+            parse_fixture::<BrokerPortfolios>(include_str!(
+                "../fixtures/scalable/v1/getBrokerPortfolios.json"
+            ))
+            .unwrap(),
+        );
+        let portfolios = portfolios.account.broker_portfolios;
+        assert_eq!(portfolios.len(), 2);
+        assert_eq!(portfolios[0].id, "3c0b6bd1");
+        let returns = &portfolios[0].valuation.time_weighted_return_by_timeframe;
+        assert_eq!(returns[3].timeframe, "ONE_YEAR");
+        assert_eq!(returns[3].performance, Some(0.1234));
+
+        let inventory = data(
+            parse_fixture::<BrokerPortfolioInventory>(include_str!(
+                "../fixtures/scalable/v1/getBrokerPortfolioInventory.json"
+            ))
+            .unwrap(),
+        );
+        let items = inventory.account.broker_portfolio.inventory.owned_items;
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].isin, "IE00B4L5Y983");
+        assert_eq!(items[0].quote_tick.as_ref().unwrap().mid_price, Some(75.0));
+        assert!(items[1].quote_tick.is_none());
+
+        let errors = parse_fixture::<BrokerPortfolios>(include_str!(
+            "../fixtures/scalable/v1/unauthorized.json"
+        ))
+        .unwrap();
+        assert!(matches!(errors, Response::Unauthenticated));
+    }
+
+    #[test]
+    fn test_errors() {
+        let result = serde_json::json!([{"errors": [
+            {"message": "Portfolio not found", "extensions": {"code": "NOT_FOUND"}},
+            {"message": "Try again later"}
+        ], "data": null}]);
+        let error = parse_response::<BrokerPortfolios>(&result).err().unwrap();
+        assert_eq!(
+            error.get_message(),
+            "Scalable getBrokerPortfolios failed: Portfolio not found, Try again later"
+        );
+    }
+
+    #[test]
+    fn test_changed_field() {
+        let result = serde_json::json!([{"data": {"account": {
+            "brokerPortfolios": [{"id": 1, "valuation": {"timeWeightedReturnByTimeframe": []}}]
+        }}}]);
+        let error = parse_response::<BrokerPortfolios>(&result).err().unwrap();
+        assert!(error
+            .get_message()
+            .contains("getBrokerPortfolios response at data.account.brokerPortfolios[0].id"));
+    }
+}
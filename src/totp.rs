@@ -0,0 +1,79 @@
+use super::error::ApiError;
+use hmac_sha1_compact::HMAC;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Time-based one-time passwords (RFC 6238) as shown by authenticator apps:
+// HMAC-SHA1, 30 second steps, 6 digits.
+
+const STEP_SECONDS: u64 = 30;
+const DIGITS: u32 = 6;
+
+// Secrets are given as base32, e.g. JBSWY3DPEHPK3PXP, spaces and padding are ignored
+fn decode_base32(secret: &String) -> Result<Vec<u8>, ApiError> {
+    let mut bytes = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in secret.chars().filter(|c| *c != ' ' && *c != '=') {
+        let value = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u32 - 'A' as u32,
+            c @ '2'..='7' => c as u32 - '2' as u32 + 26,
+            _ => {
+                return Err(ApiError::new(&format!(
+                    "Invalid character {} in TOTP secret",
+                    c
+                )))
+            }
+        };
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    if bytes.is_empty() {
+        return Err(ApiError::new(&"Empty TOTP secret".to_string()));
+    }
+    Ok(bytes)
+}
+
+fn get_code_at(key: &[u8], unix_time: u64) -> String {
+    let counter = (unix_time / STEP_SECONDS).to_be_bytes();
+    let mac = HMAC::mac(&counter, key);
+    let offset = (mac[19] & 0x0f) as usize;
+    let code = u32::from_be_bytes([
+        mac[offset],
+        mac[offset + 1],
+        mac[offset + 2],
+        mac[offset + 3],
+    ]) & 0x7fff_ffff;
+    format!(
+        "{:0width$}",
+        code % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+pub fn get_code(secret: &String) -> Result<String, ApiError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    Ok(get_code_at(&decode_base32(secret)?, now))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_get_code() {
+        // Test vectors of RFC 6238, truncated to 6 digits
+        let key = decode_base32(&"GEZDGNBVGY3TQOJQ GEZDGNBVGY3TQOJQ".to_string()).unwrap();
+        assert_eq!(key, b"12345678901234567890");
+        assert_eq!(get_code_at(&key, 59), "287082");
+        assert_eq!(get_code_at(&key, 1111111109), "081804");
+        assert!(decode_base32(&"not base32!".to_string()).is_err());
+    }
+}